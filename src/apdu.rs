@@ -1,3 +1,4 @@
+use crate::error::{APDUAnswerError, APDUErrorCode};
 use std::ops::Deref;
 
 #[derive(Debug, Clone)]
@@ -30,7 +31,28 @@ where
     }
 }
 
-#[derive(Debug)]
+impl APDUCommand<Vec<u8>> {
+    /// Parse a serialized command, `cla | ins | p1 | p2 | length | data`, the length being optional without data
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let header: &[u8; 4] = bytes.get(..4)?.try_into().ok()?;
+        let [cla, ins, p1, p2] = *header;
+        let data = match bytes[4..].split_first() {
+            None => Vec::new(),
+            Some((&len, data)) if data.len() == len as usize => data.to_vec(),
+            Some(_) => return None,
+        };
+
+        Some(Self {
+            cla,
+            ins,
+            p1,
+            p2,
+            data,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 /// An APDU answer, whole last 2 bytes are interpreted as `retcode`
 pub struct APDUAnswer<B> {
     data: B,
@@ -43,10 +65,9 @@ where
 {
     /// Attempt to interpret the given slice as an APDU answer
     pub fn from_answer(answer: B) -> eyre::Result<Self> {
-        // Todo: Investigate whay it exists
-        // if answer.len() >= 2 {
-        //     return Err(APDUAnswerError::TooShort.into());
-        // }
+        if answer.len() < 2 {
+            return Err(APDUAnswerError::TooShort.into());
+        }
         let retcode = arrayref::array_ref!(answer, answer.len() - 2, 2);
         let retcode = u16::from_be_bytes(*retcode);
 
@@ -56,28 +77,27 @@ where
         })
     }
 
-    // /// Will return the answer's payload
-    // #[inline(always)]
-    // pub fn apdu_data(&self) -> &[u8] {
-    //     &self.data[..self.data.len() - 2]
-    // }
+    /// Will return the answer's payload
+    #[inline(always)]
+    pub fn apdu_data(&self) -> &[u8] {
+        &self.data[..self.data.len() - 2]
+    }
 
-    // /// Will return the answer's payload
-    // #[inline(always)]
-    // pub fn data(&self) -> &[u8] {
-    //     self.apdu_data()
-    // }
+    /// Will return the answer's payload
+    #[inline(always)]
+    pub fn data(&self) -> &[u8] {
+        self.apdu_data()
+    }
 
-    // /// Will attempt to interpret the error code as an [APDUErrorCode],
-    // /// returning the code as is otherwise
-    // pub fn error_code(&self) -> eyre::Result<APDUErrorCode, u16> {
-    //     todo!("Parse error code into APDUErrorCode");
-    //     // self.retcode.try_into().map_err(|_| self.retcode)
-    // }
+    /// Will attempt to interpret the error code as an [APDUErrorCode],
+    /// returning the code as is otherwise
+    pub fn error_code(&self) -> Result<APDUErrorCode, u16> {
+        self.retcode.try_into().map_err(|_| self.retcode)
+    }
 
-    // /// Returns the raw return code
-    // #[inline(always)]
-    // pub fn retcode(&self) -> u16 {
-    //     self.retcode
-    // }
+    /// Returns the raw return code
+    #[inline(always)]
+    pub fn retcode(&self) -> u16 {
+        self.retcode
+    }
 }
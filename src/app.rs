@@ -0,0 +1,81 @@
+use crate::{apdu::APDUCommand, error::APDUAnswerError, queue::QueuedCommand};
+use thiserror::Error;
+
+/// A Ledger app whose commands get sent and decoded by a [LedgerAppPlugin](crate::LedgerAppPlugin), which emits an [AppResponse](crate::event::app::AppResponse) for every [AppCommand](crate::event::app::AppCommand).
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_ledger::{
+/// #     app::LedgerApp, error::APDUAnswerError, queue::QueuedCommand, LedgerAppPlugin,
+/// # };
+/// struct Boilerplate;
+///
+/// impl LedgerApp for Boilerplate {
+///     const NAME: &'static str = "Boilerplate";
+///     const CLA: u8 = 0xe0;
+///     type Command = u8;
+///     type Response = String;
+///     type State = ();
+///
+///     fn queued(value: &u8) -> eyre::Result<(QueuedCommand, ())> {
+///         let apdu = Self::apdu(0x01, 0x00, 0x00, vec![*value]);
+///         Ok((QueuedCommand::new("Echo", apdu).idempotent(), ()))
+///     }
+///
+///     fn decode(_: &u8, _: (), data: &[u8]) -> Result<String, APDUAnswerError> {
+///         String::from_utf8(data.to_vec()).map_err(|_| APDUAnswerError::Malformed("not UTF-8"))
+///     }
+/// }
+///
+/// App::new().add_plugin(LedgerAppPlugin::<Boilerplate>::default());
+/// ```
+///
+/// The [Ethereum](crate::ethereum::Ethereum), [Bitcoin](crate::bitcoin::Bitcoin) and [Solana](crate::solana::Solana) apps are implemented this way.
+pub trait LedgerApp: Send + Sync + 'static {
+    /// Name of the app as listed on the dashboard, used to open it
+    const NAME: &'static str;
+    /// Class of the app's APDUs
+    const CLA: u8;
+    /// Commands of the app, usually an enum
+    type Command: Clone + Send + Sync + 'static;
+    /// Decoded answers
+    type Response: Send + Sync + 'static;
+    /// What decoding needs besides the command, e.g. what the device yielded to an interactive command. Usually `()`.
+    type State: Send + Sync + 'static;
+
+    /// Command to push to the device's queue, with its name, APDUs and options. Invalid commands, e.g. with a malformed payload, get logged and dropped.
+    fn queued(command: &Self::Command) -> eyre::Result<(QueuedCommand, Self::State)>;
+
+    /// Decode the payload of a successful answer
+    fn decode(
+        command: &Self::Command,
+        state: Self::State,
+        data: &[u8],
+    ) -> Result<Self::Response, APDUAnswerError>;
+
+    /// APDU with the app's class
+    fn apdu(ins: u8, p1: u8, p2: u8, data: Vec<u8>) -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: Self::CLA,
+            ins,
+            p1,
+            p2,
+            data,
+        }
+    }
+}
+
+/// Why an app command didn't get a response
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("the {0} app is not open on the device")]
+    NotOpen(&'static str),
+    #[error("the user rejected the command on the device")]
+    Rejected,
+    #[error("the device answered with status {0:#06x}")]
+    Status(u16),
+    #[error("exchange failed: {0}")]
+    Exchange(String),
+    #[error(transparent)]
+    Malformed(#[from] APDUAnswerError),
+}
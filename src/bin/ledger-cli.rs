@@ -0,0 +1,547 @@
+use bevy::{
+    app::{AppExit, ScheduleRunnerSettings},
+    ecs::event::ManualEventReader,
+    prelude::*,
+};
+use bevy_ledger::{
+    apdu::APDUCommand,
+    config::LedgerConfig,
+    device::{Device, LockState},
+    event::{general::*, queue::CommandCompleted},
+    queue::CommandQueue,
+    LedgerPlugins,
+};
+use serde_json::{json, Value};
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const USAGE: &str = "Usage: ledger-cli [OPTIONS] <COMMAND>
+
+Commands:
+  list          List the connected devices
+  info          Show the firmware versions
+  app           Show the app running on the device
+  open <name>   Open an app
+  quit          Quit the running app
+  name          Show the device name
+  apps          List the installed apps
+  raw <hex>     Send an APDU, e.g. `b001000000`
+
+Options:
+  --transport <hid|tcp>   How to reach the device [default: hid]
+  --address <host:port>   Speculos APDU server, with the tcp transport [default: 127.0.0.1:9999]
+  --device <index>        Device to use, as listed by `list` [default: 0]
+  --json                  Print JSON instead of text
+  -h, --help              Print this help";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Transport {
+    Hid,
+    Tcp(SocketAddr),
+}
+
+#[derive(Clone)]
+enum Command {
+    List,
+    Info,
+    App,
+    Open(String),
+    Quit,
+    Name,
+    Apps,
+    Raw(APDUCommand<Vec<u8>>),
+}
+
+#[derive(Resource)]
+struct Cli {
+    command: Command,
+    transport: Transport,
+    device: usize,
+    json: bool,
+}
+
+impl Cli {
+    /// Parse the arguments, `None` if the help got asked for
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut command = None;
+        let mut transport = "hid".to_string();
+        let mut address = "127.0.0.1:9999".to_string();
+        let mut device = 0;
+        let mut json = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("missing value for {name}"));
+            match arg.as_str() {
+                "--transport" => transport = value("--transport")?,
+                "--address" => address = value("--address")?,
+                "--device" => {
+                    device = value("--device")?
+                        .parse()
+                        .map_err(|_| "invalid device index".to_string())?
+                }
+                "--json" => json = true,
+                "-h" | "--help" => return Ok(None),
+                _ if command.is_some() => return Err(format!("unexpected argument `{arg}`")),
+                "list" => command = Some(Command::List),
+                "info" => command = Some(Command::Info),
+                "app" => command = Some(Command::App),
+                "open" => command = Some(Command::Open(value("open")?)),
+                "quit" => command = Some(Command::Quit),
+                "name" => command = Some(Command::Name),
+                "apps" => command = Some(Command::Apps),
+                "raw" => {
+                    let apdu = hex::decode(value("raw")?)
+                        .ok()
+                        .and_then(|bytes| APDUCommand::from_bytes(&bytes))
+                        .ok_or("invalid APDU, expected `cla ins p1 p2 [length data]` in hex")?;
+                    command = Some(Command::Raw(apdu));
+                }
+                _ => return Err(format!("unknown command `{arg}`\n\n{USAGE}")),
+            }
+        }
+
+        let transport = match transport.as_str() {
+            "hid" => Transport::Hid,
+            "tcp" => Transport::Tcp(
+                address
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut a| a.next())
+                    .ok_or(format!("invalid address `{address}`"))?,
+            ),
+            _ => return Err(format!("unknown transport `{transport}`")),
+        };
+
+        Ok(Some(Self {
+            command: command.ok_or(USAGE)?,
+            transport,
+            device,
+            json,
+        }))
+    }
+
+    /// Print a result, as text or JSON
+    fn print(&self, text: impl FnOnce() -> String, json: Value) {
+        if self.json {
+            println!("{json}");
+        } else {
+            println!("{}", text());
+        }
+    }
+}
+
+/// Process exit code, read back once the app exited
+#[derive(Resource, Clone, Default)]
+struct ExitStatus(Arc<AtomicI32>);
+
+impl ExitStatus {
+    /// Print the error and exit with a failure code
+    fn fail(&self, cli: &Cli, err: &str) {
+        match cli.json {
+            true => println!("{}", json!({ "error": err })),
+            false => eprintln!("error: {err}"),
+        }
+        self.0.store(1, Ordering::Relaxed);
+    }
+}
+
+fn main() {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(Some(cli)) => cli,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+    let status = ExitStatus::default();
+
+    App::new()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(10)))
+        .insert_resource(LedgerConfig {
+            heartbeat_interval: None,
+            ..default()
+        })
+        .insert_resource(cli)
+        .insert_resource(status.clone())
+        .add_plugins(MinimalPlugins)
+        .add_plugins(LedgerPlugins)
+        .add_startup_system(connect)
+        // Once the devices found by the scan got spawned
+        .add_system(dispatch.in_base_set(CoreSet::PostUpdate))
+        .add_systems((
+            check_completed,
+            check_malformed,
+            print_version,
+            print_app,
+            print_name,
+            print_apps,
+            print_raw,
+        ))
+        .run();
+
+    std::process::exit(status.0.load(Ordering::Relaxed));
+}
+
+fn connect(cli: Res<Cli>, mut scan: EventWriter<ScanDevices>, mut commands: Commands) {
+    match cli.transport {
+        Transport::Hid => scan.send(ScanDevices),
+        Transport::Tcp(address) => {
+            commands.spawn((
+                Device::tcp(address),
+                CommandQueue::default(),
+                LockState::default(),
+            ));
+        }
+    }
+}
+
+/// Send the command to the chosen device once the devices got scanned
+fn dispatch(
+    world: &mut World,
+    mut scanned: Local<ManualEventReader<DevicesScanned>>,
+    mut dispatched: Local<bool>,
+) {
+    let cli = world.resource::<Cli>();
+    let ready = match cli.transport {
+        Transport::Hid => {
+            let events = world.resource::<Events<DevicesScanned>>();
+            scanned.iter(events).next().is_some()
+        }
+        // Spawned on startup
+        Transport::Tcp(_) => true,
+    };
+    if *dispatched || !ready {
+        return;
+    }
+    *dispatched = true;
+
+    // Same indexes from one run to the other, whatever the order the devices got spawned in
+    let mut devices: Vec<(Entity, Device)> = world
+        .query::<(Entity, &Device)>()
+        .iter(world)
+        .map(|(id, device)| (id, device.clone()))
+        .collect();
+    devices.sort_by(|(_, a), (_, b)| {
+        (a.path(), a.serial_number()).cmp(&(b.path(), b.serial_number()))
+    });
+
+    let cli = world.resource::<Cli>();
+    if let Command::List = cli.command {
+        let paths: Vec<String> = devices
+            .iter()
+            .map(|(_, d)| {
+                d.path()
+                    .map_or(String::new(), |p| p.to_string_lossy().into_owned())
+            })
+            .collect();
+        cli.print(
+            || {
+                devices
+                    .iter()
+                    .zip(&paths)
+                    .enumerate()
+                    .map(|(index, ((_, device), path))| {
+                        format!("{index}  {}  {device}  {path}", device.model())
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+            devices
+                .iter()
+                .zip(&paths)
+                .enumerate()
+                .map(|(index, ((_, device), path))| {
+                    json!({
+                        "index": index,
+                        "device": device.to_string(),
+                        "model": device.model().to_string(),
+                        "path": path,
+                    })
+                })
+                .collect(),
+        );
+        world.send_event(AppExit);
+        return;
+    }
+
+    let Some(&(device_id, _)) = devices.get(cli.device) else {
+        let err = match devices.len() {
+            0 => "no device found, make sure it is connected and unlocked".to_string(),
+            n => format!("no device {}, found {n}", cli.device),
+        };
+        world.resource::<ExitStatus>().fail(cli, &err);
+        world.send_event(AppExit);
+        return;
+    };
+
+    match cli.command.clone() {
+        Command::List => unreachable!(),
+        Command::Info => world.send_event(GetVersion { device_id }),
+        Command::App => world.send_event(GetAppAndVersion { device_id }),
+        Command::Open(name) => world.send_event(OpenApp { device_id, name }),
+        Command::Quit => world.send_event(QuitApp { device_id }),
+        Command::Name => world.send_event(GetDeviceName { device_id }),
+        Command::Apps => world.send_event(ListApps { device_id }),
+        Command::Raw(apdu) => world.send_event(SendRawApdu { device_id, apdu }),
+    }
+}
+
+/// Fail on errors, and print the commands answered by their status only
+fn check_completed(
+    mut events: EventReader<CommandCompleted>,
+    cli: Res<Cli>,
+    status: Res<ExitStatus>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        let result = match &e.result {
+            Ok(answer) if answer.retcode() == 0x9000 => match cli.command {
+                Command::Open(_) | Command::Quit => Ok(()),
+                _ => return,
+            },
+            // Raw answers are printed whatever their status
+            Ok(_) if matches!(cli.command, Command::Raw(_)) => return,
+            Ok(answer) => Err(format!(
+                "the device answered with status {:#06x}",
+                answer.retcode()
+            )),
+            Err(err) => Err(err.to_string()),
+        };
+
+        match result {
+            Ok(()) => cli.print(|| "ok".to_string(), json!({ "status": "ok" })),
+            Err(err) => status.fail(&cli, &err),
+        }
+        exit.send(AppExit);
+    });
+}
+
+/// Fail on successful answers which cannot be decoded, rather than waiting for them forever
+fn check_malformed(
+    mut events: EventReader<AnswerMalformed>,
+    cli: Res<Cli>,
+    status: Res<ExitStatus>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        status.fail(&cli, &format!("cannot decode the answer to {}", e.name));
+        exit.send(AppExit);
+    });
+}
+
+fn print_version(
+    mut events: EventReader<VersionReceived>,
+    cli: Res<Cli>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        let v = &e.version;
+        cli.print(
+            || {
+                format!(
+                    "target id:    {:#010x}\nse version:   {}\nmcu version:  {}\nflags:        {}",
+                    v.target_id,
+                    v.se_version,
+                    v.mcu_version,
+                    hex::encode(&v.flags)
+                )
+            },
+            json!({
+                "target_id": format!("{:#010x}", v.target_id),
+                "se_version": v.se_version,
+                "mcu_version": v.mcu_version,
+                "flags": hex::encode(&v.flags),
+            }),
+        );
+        exit.send(AppExit);
+    });
+}
+
+fn print_app(
+    mut events: EventReader<AppAndVersionReceived>,
+    cli: Res<Cli>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        cli.print(
+            || format!("{} {}", e.app.name, e.app.version),
+            json!({ "name": e.app.name, "version": e.app.version }),
+        );
+        exit.send(AppExit);
+    });
+}
+
+fn print_name(
+    mut events: EventReader<DeviceNameReceived>,
+    cli: Res<Cli>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        cli.print(|| e.name.clone(), json!({ "name": e.name }));
+        exit.send(AppExit);
+    });
+}
+
+fn print_apps(mut events: EventReader<AppsListed>, cli: Res<Cli>, mut exit: EventWriter<AppExit>) {
+    events.iter().for_each(|e| {
+        cli.print(
+            || {
+                e.apps
+                    .iter()
+                    .map(|app| format!("{}  {}", app.name, hex::encode(app.hash)))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+            e.apps
+                .iter()
+                .map(|app| {
+                    json!({
+                        "name": app.name,
+                        "hash": hex::encode(app.hash),
+                        "hash_code_data": hex::encode(app.hash_code_data),
+                        "blocks": app.blocks,
+                        "flags": app.flags,
+                    })
+                })
+                .collect(),
+        );
+        exit.send(AppExit);
+    });
+}
+
+fn print_raw(
+    mut events: EventReader<RawApduAnswered>,
+    cli: Res<Cli>,
+    status: Res<ExitStatus>,
+    mut exit: EventWriter<AppExit>,
+) {
+    events.iter().for_each(|e| {
+        match &e.result {
+            Ok(answer) => {
+                let data = hex::encode(answer.data());
+                let sw = format!("{:04x}", answer.retcode());
+                cli.print(
+                    || format!("{data} {sw}"),
+                    json!({ "data": data, "status": sw }),
+                );
+            }
+            Err(err) => status.fail(&cli, err),
+        }
+        exit.send(AppExit);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Option<Cli>, String> {
+        Cli::parse(args.split_whitespace().map(String::from))
+    }
+
+    fn command(args: &str) -> Command {
+        parse(args).unwrap().unwrap().command
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(command("list"), Command::List));
+        assert!(matches!(command("info"), Command::Info));
+        assert!(matches!(command("app"), Command::App));
+        assert!(matches!(command("open Ethereum"), Command::Open(name) if name == "Ethereum"));
+        assert!(matches!(command("quit"), Command::Quit));
+        assert!(matches!(command("name"), Command::Name));
+        assert!(matches!(command("apps"), Command::Apps));
+
+        let Command::Raw(apdu) = command("raw e0d8000003414243") else {
+            panic!("expected a raw APDU");
+        };
+        assert_eq!(apdu.serialize(), hex::decode("e0d8000003414243").unwrap());
+        let Command::Raw(apdu) = command("raw b0010000") else {
+            panic!("expected a raw APDU");
+        };
+        assert_eq!(apdu.serialize(), hex::decode("b001000000").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_commands() {
+        assert_eq!(parse("").err(), Some(USAGE.to_string()));
+        assert_eq!(
+            parse("open").err(),
+            Some("missing value for open".to_string())
+        );
+        assert_eq!(
+            parse("app name").err(),
+            Some("unexpected argument `name`".to_string())
+        );
+        let err = parse("sign").err().unwrap();
+        assert!(err.starts_with("unknown command `sign`"));
+
+        // Not hex, too short, length not matching the data
+        ["raw zz", "raw b001", "raw e0d8000005414243"]
+            .into_iter()
+            .for_each(|args| {
+                let err = parse(args).err().unwrap();
+                assert!(err.starts_with("invalid APDU"), "{args}");
+            });
+    }
+
+    #[test]
+    fn parses_options() {
+        let cli = parse("app").unwrap().unwrap();
+        assert_eq!(cli.transport, Transport::Hid);
+        assert_eq!(cli.device, 0);
+        assert!(!cli.json);
+
+        let cli = parse("--json --device 2 app").unwrap().unwrap();
+        assert_eq!(cli.device, 2);
+        assert!(cli.json);
+
+        // Options can follow the command
+        let cli = parse("app --transport tcp").unwrap().unwrap();
+        let speculos = "127.0.0.1:9999".parse().unwrap();
+        assert_eq!(cli.transport, Transport::Tcp(speculos));
+
+        let cli = parse("--transport tcp --address 127.0.0.1:40000 app")
+            .unwrap()
+            .unwrap();
+        let address = "127.0.0.1:40000".parse().unwrap();
+        assert_eq!(cli.transport, Transport::Tcp(address));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert_eq!(
+            parse("--transport usb app").err(),
+            Some("unknown transport `usb`".to_string())
+        );
+        assert_eq!(
+            parse("--transport tcp --address nowhere app").err(),
+            Some("invalid address `nowhere`".to_string())
+        );
+        assert_eq!(
+            parse("--device first app").err(),
+            Some("invalid device index".to_string())
+        );
+        assert_eq!(
+            parse("app --device").err(),
+            Some("missing value for --device".to_string())
+        );
+    }
+
+    #[test]
+    fn help_is_not_an_error() {
+        assert!(matches!(parse("--help"), Ok(None)));
+        assert!(matches!(parse("app -h"), Ok(None)));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::path::DerivationPath;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{collections::BTreeMap, fs, path::Path};
+use thiserror::Error;
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PsbtError {
+    #[error("PSBT: invalid magic bytes")]
+    InvalidMagic,
+    #[error("PSBT: unexpected end of data")]
+    UnexpectedEnd,
+    #[error("PSBT: duplicate key {0}")]
+    DuplicateKey(String),
+    #[error("PSBT: version {0} is not supported, convert it to PSBT v2 first")]
+    UnsupportedVersion(u32),
+    #[error("PSBT: missing input or output count")]
+    MissingCount,
+    #[error("PSBT: trailing bytes after the last output")]
+    TrailingBytes,
+    #[error("PSBT: invalid base64")]
+    InvalidBase64,
+}
+
+/// Key-value pairs of a PSBT map, keys including their type byte
+pub type PsbtMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Partially signed Bitcoin transaction (BIP-370, version 2), as expected by the Bitcoin app
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Psbt {
+    pub global: PsbtMap,
+    pub inputs: Vec<PsbtMap>,
+    pub outputs: Vec<PsbtMap>,
+}
+
+impl Psbt {
+    /// Parse a binary PSBT
+    pub fn from_bytes(data: &[u8]) -> Result<Self, PsbtError> {
+        let mut data = data
+            .strip_prefix(PSBT_MAGIC)
+            .ok_or(PsbtError::InvalidMagic)?;
+
+        let global = read_map(&mut data)?;
+        let version = match global.get([PSBT_GLOBAL_VERSION].as_slice()) {
+            Some(v) if v.len() == 4 => u32::from_le_bytes([v[0], v[1], v[2], v[3]]),
+            Some(_) => return Err(PsbtError::UnexpectedEnd),
+            None => 0,
+        };
+        if version != 2 {
+            return Err(PsbtError::UnsupportedVersion(version));
+        }
+
+        let count = |key: u8| -> Result<u64, PsbtError> {
+            let mut value = global
+                .get([key].as_slice())
+                .map(Vec::as_slice)
+                .ok_or(PsbtError::MissingCount)?;
+            read_compact_size(&mut value)
+        };
+        let (input_count, output_count) = (
+            count(PSBT_GLOBAL_INPUT_COUNT)?,
+            count(PSBT_GLOBAL_OUTPUT_COUNT)?,
+        );
+
+        let inputs = (0..input_count)
+            .map(|_| read_map(&mut data))
+            .collect::<Result<_, _>>()?;
+        let outputs = (0..output_count)
+            .map(|_| read_map(&mut data))
+            .collect::<Result<_, _>>()?;
+        if !data.is_empty() {
+            return Err(PsbtError::TrailingBytes);
+        }
+
+        Ok(Self {
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Parse a base64 encoded PSBT, as exchanged by most wallets
+    pub fn from_base64(encoded: &str) -> Result<Self, PsbtError> {
+        let data = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| PsbtError::InvalidBase64)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Read a PSBT file, either binary or base64 encoded
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(PSBT_MAGIC) {
+            return Ok(Self::from_bytes(&data)?);
+        }
+
+        let encoded = std::str::from_utf8(&data).map_err(|_| PsbtError::InvalidMagic)?;
+        Ok(Self::from_base64(encoded)?)
+    }
+}
+
+/// Wallet policy (BIP-388) describing the scripts of an account, e.g. `wpkh(@0/**)` with the key `[f5acc2fd/84'/1'/0']tpubDCtKfsNyRhULjZ9XMS4VKKtVcPdVDi8MKUbcSD9MJDyjRu1A2ND5MiipozyyspBT9bg8upEp7a8EAgFxNxXn1d7QkdbL52Ty5jiSLcxPt1P`.
+///
+/// Standard single signature policies can be used right away, other policies must be registered on the device first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalletPolicy {
+    /// Name shown on the device, empty for standard policies
+    pub name: String,
+    /// Descriptor with keys replaced by `@i` placeholders
+    pub descriptor_template: String,
+    /// Keys referenced by the placeholders, with their origin when they belong to the device
+    pub keys_info: Vec<String>,
+}
+
+impl WalletPolicy {
+    pub fn new(
+        name: impl Into<String>,
+        descriptor_template: impl Into<String>,
+        keys_info: Vec<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            descriptor_template: descriptor_template.into(),
+            keys_info,
+        }
+    }
+}
+
+/// The Bitcoin app. Send it [AppCommand](crate::event::app::AppCommand)`<Bitcoin>`s and read the [AppResponse](crate::event::app::AppResponse)`<Bitcoin>`s, the app gets opened first if needed.
+pub struct Bitcoin;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BtcCommand {
+    /// Get the fingerprint of the master key, needed to build key origins
+    GetMasterFingerprint,
+    /// Get the extended public key of a BIP-32 path
+    GetExtendedPubkey {
+        path: DerivationPath,
+        /// Display the key on the device and wait for the user to confirm it
+        display: bool,
+    },
+    /// Register a wallet policy, e.g. a multisig, after the user reviewed it on the device
+    RegisterWallet(WalletPolicy),
+    /// Get an address of a wallet policy
+    GetWalletAddress {
+        policy: WalletPolicy,
+        /// HMAC returned when registering the policy, `None` for standard policies
+        hmac: Option<[u8; 32]>,
+        change: bool,
+        address_index: u32,
+        /// Display the address on the device and wait for the user to confirm it
+        display: bool,
+    },
+    /// Sign the inputs of a PSBT spending from a wallet policy, the user has to approve the transaction
+    SignPsbt {
+        psbt: Psbt,
+        policy: WalletPolicy,
+        /// HMAC returned when registering the policy, `None` for standard policies
+        hmac: Option<[u8; 32]>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BtcResponse {
+    MasterFingerprint([u8; 4]),
+    /// Base58 encoded, e.g. `xpub…` or `tpub…` depending on the network of the app
+    ExtendedPubkey(String),
+    /// The HMAC must be stored to use the policy later on
+    WalletRegistered {
+        wallet_id: [u8; 32],
+        hmac: [u8; 32],
+    },
+    WalletAddress(String),
+    PsbtSigned(Vec<BtcPartialSignature>),
+}
+
+/// Signature of a PSBT input, to be added to the PSBT as a partial or taproot signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BtcPartialSignature {
+    pub input_index: usize,
+    /// Compressed public key for ECDSA signatures, x-only key for Schnorr signatures
+    pub pubkey: Vec<u8>,
+    /// Leaf hash when signing a taproot script path spend
+    pub tapleaf_hash: Option<[u8; 32]>,
+    /// DER encoded with the sighash type for ECDSA, 64 or 65 bytes for Schnorr
+    pub signature: Vec<u8>,
+}
+
+/// Read a Bitcoin compact size unsigned integer off the front of `data`
+pub(crate) fn read_compact_size(data: &mut &[u8]) -> Result<u64, PsbtError> {
+    let (prefix, rest) = data.split_first().ok_or(PsbtError::UnexpectedEnd)?;
+    let len = match prefix {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        _ => {
+            *data = rest;
+            return Ok(*prefix as u64);
+        }
+    };
+
+    let bytes = rest.get(..len).ok_or(PsbtError::UnexpectedEnd)?;
+    let mut value = [0; 8];
+    value[..len].copy_from_slice(bytes);
+    *data = &rest[len..];
+    Ok(u64::from_le_bytes(value))
+}
+
+pub(crate) fn write_compact_size(value: u64) -> Vec<u8> {
+    match value {
+        0..=0xfc => vec![value as u8],
+        0xfd..=0xffff => [&[0xfd], &(value as u16).to_le_bytes()[..]].concat(),
+        0x1_0000..=0xffff_ffff => [&[0xfe], &(value as u32).to_le_bytes()[..]].concat(),
+        _ => [&[0xff], &value.to_le_bytes()[..]].concat(),
+    }
+}
+
+/// Read a length prefixed field off the front of `data`
+fn read_prefixed<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], PsbtError> {
+    let len = read_compact_size(data)? as usize;
+    if data.len() < len {
+        return Err(PsbtError::UnexpectedEnd);
+    }
+
+    let (field, rest) = data.split_at(len);
+    *data = rest;
+    Ok(field)
+}
+
+/// Read key-value pairs up to the `0x00` separator
+fn read_map(data: &mut &[u8]) -> Result<PsbtMap, PsbtError> {
+    let mut map = PsbtMap::new();
+
+    loop {
+        let key = read_prefixed(data)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+
+        let value = read_prefixed(data)?;
+        if map.insert(key.to_vec(), value.to_vec()).is_some() {
+            return Err(PsbtError::DuplicateKey(hex::encode(key)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(key: &[u8], value: &[u8]) -> Vec<u8> {
+        [
+            write_compact_size(key.len() as u64),
+            key.to_vec(),
+            write_compact_size(value.len() as u64),
+            value.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// PSBT v2 with one input spending the output 0 of `txid`, and one output of `amount` sats
+    fn psbt_v2(global: &[Vec<u8>], txid: [u8; 32], amount: u64) -> Vec<u8> {
+        [
+            PSBT_MAGIC.to_vec(),
+            global.concat(),
+            vec![0x00],
+            pair(&[0x0e], &txid),
+            pair(&[0x0f], &0u32.to_le_bytes()),
+            vec![0x00],
+            pair(&[0x03], &amount.to_le_bytes()),
+            pair(&[0x04], &[0x00, 0x14]),
+            vec![0x00],
+        ]
+        .concat()
+    }
+
+    fn global_v2() -> Vec<Vec<u8>> {
+        vec![
+            pair(&[PSBT_GLOBAL_VERSION], &2u32.to_le_bytes()),
+            pair(&[PSBT_GLOBAL_INPUT_COUNT], &[0x01]),
+            pair(&[PSBT_GLOBAL_OUTPUT_COUNT], &[0x01]),
+        ]
+    }
+
+    #[test]
+    fn compact_size_boundaries() {
+        let cases: [(u64, &[u8]); 7] = [
+            (0, &[0x00]),
+            (0xfc, &[0xfc]),
+            (0xfd, &[0xfd, 0xfd, 0x00]),
+            (0xffff, &[0xfd, 0xff, 0xff]),
+            (0x1_0000, &[0xfe, 0x00, 0x00, 0x01, 0x00]),
+            (0xffff_ffff, &[0xfe, 0xff, 0xff, 0xff, 0xff]),
+            (0x1_0000_0000, &[0xff, 0, 0, 0, 0, 0x01, 0, 0, 0]),
+        ];
+
+        cases.into_iter().for_each(|(value, encoded)| {
+            assert_eq!(write_compact_size(value), encoded, "{value:#x}");
+
+            let data = [encoded, &[0xaa]].concat();
+            let mut rest = data.as_slice();
+            assert_eq!(read_compact_size(&mut rest), Ok(value));
+            assert_eq!(rest, [0xaa]);
+        });
+    }
+
+    #[test]
+    fn truncated_compact_size() {
+        assert_eq!(
+            read_compact_size(&mut &[][..]),
+            Err(PsbtError::UnexpectedEnd)
+        );
+        assert_eq!(
+            read_compact_size(&mut &[0xfd, 0xff][..]),
+            Err(PsbtError::UnexpectedEnd)
+        );
+        assert_eq!(
+            read_compact_size(&mut &[0xfe, 0, 0, 0][..]),
+            Err(PsbtError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn parses_psbt_v2_maps() {
+        let psbt = Psbt::from_bytes(&psbt_v2(&global_v2(), [0x11; 32], 50_000)).unwrap();
+
+        assert_eq!(psbt.global.len(), 3);
+        assert_eq!(psbt.global[[PSBT_GLOBAL_VERSION].as_slice()], [2, 0, 0, 0]);
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.inputs[0][[0x0e].as_slice()], [0x11; 32]);
+        assert_eq!(psbt.outputs.len(), 1);
+        assert_eq!(psbt.outputs[0][[0x03].as_slice()], 50_000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn parses_base64_psbt() {
+        let data = psbt_v2(&global_v2(), [0x11; 32], 50_000);
+        let encoded = format!("{}\n", STANDARD.encode(&data));
+
+        assert_eq!(Psbt::from_base64(&encoded), Psbt::from_bytes(&data));
+        assert_eq!(Psbt::from_base64("psbt!"), Err(PsbtError::InvalidBase64));
+    }
+
+    #[test]
+    fn rejects_malformed_psbts() {
+        let tx = [0x11; 32];
+
+        let v0 = psbt_v2(&global_v2()[1..], tx, 1);
+        assert_eq!(Psbt::from_bytes(&v0), Err(PsbtError::UnsupportedVersion(0)));
+
+        let no_count = psbt_v2(&global_v2()[..2], tx, 1);
+        assert_eq!(Psbt::from_bytes(&no_count), Err(PsbtError::MissingCount));
+
+        let mut duplicate = global_v2();
+        duplicate.push(pair(&[PSBT_GLOBAL_INPUT_COUNT], &[0x02]));
+        assert_eq!(
+            Psbt::from_bytes(&psbt_v2(&duplicate, tx, 1)),
+            Err(PsbtError::DuplicateKey("04".to_string()))
+        );
+
+        let valid = psbt_v2(&global_v2(), tx, 1);
+        assert_eq!(Psbt::from_bytes(&valid[1..]), Err(PsbtError::InvalidMagic));
+        assert_eq!(
+            Psbt::from_bytes(&valid[..valid.len() - 1]),
+            Err(PsbtError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Psbt::from_bytes(&[valid, vec![0x00]].concat()),
+            Err(PsbtError::TrailingBytes)
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{constant::HEARTBEAT, queue::QueuedCommand};
+use bevy::ecs::system::Resource;
+use std::{collections::HashMap, time::Duration};
+
+/// Default timeout of commands the device answers right away
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Default timeout of commands waiting for the user to press buttons on the device
+pub const USER_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Plugin wide settings. Insert it before adding `LedgerPlugins` to override the defaults.
+#[derive(Resource, Clone, Debug)]
+pub struct LedgerConfig {
+    /// How long to wait for the device to answer a command
+    pub timeout: Duration,
+    /// How long to wait for the device to answer a command [waiting on the user](QueuedCommand::with_user_confirmation)
+    pub confirmation_timeout: Duration,
+    /// Timeout overrides by command name
+    pub command_timeouts: HashMap<&'static str, Duration>,
+    /// How to retry idempotent commands failing with a transient HID error
+    pub retry: RetryPolicy,
+    /// How often idle devices get polled to keep their [LockState](crate::device::LockState) up to date, `None` to disable
+    pub heartbeat_interval: Option<Duration>,
+    /// How long to wait for a device to come back after it disconnected to switch apps
+    pub app_switch_timeout: Duration,
+}
+
+impl LedgerConfig {
+    /// Timeout applying to the given command
+    pub fn timeout_for(&self, command: &QueuedCommand) -> Duration {
+        command
+            .timeout
+            .or_else(|| self.command_timeouts.get(command.name).copied())
+            .unwrap_or(if command.user_confirmation {
+                self.confirmation_timeout
+            } else {
+                self.timeout
+            })
+    }
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            confirmation_timeout: USER_CONFIRMATION_TIMEOUT,
+            command_timeouts: HashMap::from([(HEARTBEAT, Duration::from_secs(2))]),
+            retry: RetryPolicy::default(),
+            heartbeat_interval: Some(Duration::from_secs(10)),
+            app_switch_timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Retry policy for commands opting in with [QueuedCommand::idempotent](crate::queue::QueuedCommand::idempotent)
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of exchanges, including the first one
+    pub max_attempts: u32,
+    /// Delay between two attempts
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
@@ -5,10 +5,64 @@ pub const LEDGER_CHANNEL: u16 = 0x0101;
 // so the actual buffer is 64 bytes
 pub const LEDGER_PACKET_WRITE_SIZE: u8 = 65;
 pub const LEDGER_PACKET_READ_SIZE: u8 = 64;
-pub const LEDGER_TIMEOUT: i32 = 10_000_000;
+/// Name of the command polling idle devices for their lock state
+pub const HEARTBEAT: &str = "Heartbeat";
+pub const GET_APP_AND_VERSION: &str = "GetAppAndVersion";
+pub const OPEN_APP: &str = "OpenApp";
+pub const QUIT_APP: &str = "QuitApp";
+pub const GET_VERSION: &str = "GetVersion";
+pub const GET_DEVICE_NAME: &str = "GetDeviceName";
+pub const LIST_APPS: &str = "ListApps";
+pub const GET_BATTERY_STATE: &str = "GetBatteryState";
+pub const RAW_APDU: &str = "RawApdu";
+
+pub const CLA_APP_AND_VERSION: u8 = 0xb0;
+pub const INS_APP_AND_VERSION: u8 = 0x01;
+
+pub const CLA_QUIT_APP: u8 = 0xb0;
+pub const INS_QUIT_APP: u8 = 0xa7;
 
 pub const CLA_DEVICE_INFO: u8 = 0xe0;
 pub const INS_DEVICE_INFO: u8 = 0x01;
 
 pub const CLA_OPEN_APP: u8 = 0xe0;
 pub const INS_OPEN_APP: u8 = 0xd8;
+
+pub const CLA_DEVICE_NAME: u8 = 0xe0;
+pub const INS_GET_DEVICE_NAME: u8 = 0xd2;
+
+pub const CLA_BATTERY_STATE: u8 = 0xe0;
+pub const INS_GET_BATTERY_STATE: u8 = 0x10;
+pub const P2_BATTERY_PERCENTAGE: u8 = 0x00;
+
+pub const CLA_LIST_APPS: u8 = 0xe0;
+pub const INS_LIST_APPS: u8 = 0xde;
+pub const INS_LIST_APPS_CONTINUE: u8 = 0xdf;
+
+pub const CLA_ETH: u8 = 0xe0;
+pub const INS_ETH_GET_ADDRESS: u8 = 0x02;
+pub const INS_ETH_SIGN_TRANSACTION: u8 = 0x04;
+pub const INS_ETH_GET_APP_CONFIGURATION: u8 = 0x06;
+pub const INS_ETH_SIGN_PERSONAL_MESSAGE: u8 = 0x08;
+pub const INS_ETH_PROVIDE_ERC20_TOKEN_INFO: u8 = 0x0a;
+pub const INS_ETH_SIGN_EIP712: u8 = 0x0c;
+pub const INS_ETH_SET_EXTERNAL_PLUGIN: u8 = 0x12;
+pub const INS_ETH_PROVIDE_NFT_INFO: u8 = 0x14;
+pub const INS_ETH_EIP712_STRUCT_DEFINITION: u8 = 0x1a;
+pub const INS_ETH_EIP712_STRUCT_IMPLEMENTATION: u8 = 0x1c;
+
+pub const CLA_BTC: u8 = 0xe1;
+pub const INS_BTC_GET_EXTENDED_PUBKEY: u8 = 0x00;
+pub const INS_BTC_REGISTER_WALLET: u8 = 0x02;
+pub const INS_BTC_GET_WALLET_ADDRESS: u8 = 0x03;
+pub const INS_BTC_SIGN_PSBT: u8 = 0x04;
+pub const INS_BTC_GET_MASTER_FINGERPRINT: u8 = 0x05;
+pub const BTC_PROTOCOL_VERSION: u8 = 0x01;
+pub const CLA_BTC_FRAMEWORK: u8 = 0xf8;
+pub const INS_BTC_CONTINUE_INTERRUPTED: u8 = 0x01;
+pub const SW_BTC_INTERRUPTED_EXECUTION: u16 = 0xe000;
+
+pub const CLA_SOL: u8 = 0xe0;
+pub const INS_SOL_GET_APP_CONFIGURATION: u8 = 0x04;
+pub const INS_SOL_GET_PUBKEY: u8 = 0x05;
+pub const INS_SOL_SIGN_MESSAGE: u8 = 0x06;
@@ -1,36 +1,309 @@
-use crate::constant::{LEDGER_USAGE_PAGE, LEDGER_VID};
-use bevy::ecs::component::Component;
-use hidapi::{DeviceInfo, HidApi, HidDevice, HidResult};
-use std::fmt;
+use crate::{
+    constant::{LEDGER_USAGE_PAGE, LEDGER_VID},
+    replay::ReplaySession,
+};
+use bevy::ecs::{component::Component, entity::Entity, system::Resource};
+use hidapi::DeviceInfo;
+use std::{ffi::CStr, fmt, net::SocketAddr, sync::Arc};
 
-#[derive(Component)]
+/// How a device is reached
+#[derive(Clone)]
+pub enum DeviceKind {
+    /// USB HID device
+    Hid(DeviceInfo),
+    /// Answers replayed from a recorded session
+    Replay(Arc<ReplaySession>),
+    /// APDU server of the Speculos emulator
+    Tcp(SocketAddr),
+}
+
+#[derive(Component, Clone)]
 pub struct Device {
-    inner: DeviceInfo,
+    kind: DeviceKind,
 }
 
 impl Device {
+    /// Device answering from a recorded session. It isn't affected by scans, spawn it along with a [CommandQueue](crate::queue::CommandQueue) and a [LockState].
+    pub fn replay(session: ReplaySession) -> Self {
+        Self {
+            kind: DeviceKind::Replay(Arc::new(session)),
+        }
+    }
+
+    /// Device emulated by Speculos, listening for APDUs on the given address. Like replayed devices, it isn't affected by scans.
+    pub fn tcp(address: SocketAddr) -> Self {
+        Self {
+            kind: DeviceKind::Tcp(address),
+        }
+    }
+
+    pub fn kind(&self) -> &DeviceKind {
+        &self.kind
+    }
+
     pub fn is_ledger(&self) -> bool {
-        self.inner.vendor_id() == LEDGER_VID && self.inner.usage_page() == LEDGER_USAGE_PAGE
+        match &self.kind {
+            DeviceKind::Hid(info) => {
+                info.vendor_id() == LEDGER_VID && info.usage_page() == LEDGER_USAGE_PAGE
+            }
+            DeviceKind::Replay(_) | DeviceKind::Tcp(_) => true,
+        }
     }
 
-    pub fn open(&self, hidapi: &HidApi) -> HidResult<HidDevice> {
-        self.inner.open_device(&hidapi)
+    /// Platform specific path identifying the device while it stays connected, `None` for devices not reached through USB
+    pub fn path(&self) -> Option<&CStr> {
+        match &self.kind {
+            DeviceKind::Hid(info) => Some(info.path()),
+            DeviceKind::Replay(_) | DeviceKind::Tcp(_) => None,
+        }
+    }
+
+    /// Serial number reported over USB, which survives the re-enumeration of an app switch unlike the path
+    pub fn serial_number(&self) -> Option<&str> {
+        match &self.kind {
+            DeviceKind::Hid(info) => info.serial_number(),
+            DeviceKind::Replay(_) | DeviceKind::Tcp(_) => None,
+        }
+    }
+
+    pub fn model(&self) -> DeviceModel {
+        match &self.kind {
+            DeviceKind::Hid(info) => DeviceModel::from_product_id(info.product_id()),
+            DeviceKind::Replay(session) => session
+                .product_id()
+                .map_or(DeviceModel::Unknown, DeviceModel::from_product_id),
+            DeviceKind::Tcp(_) => DeviceModel::Unknown,
+        }
     }
 }
 
 impl From<DeviceInfo> for Device {
-    fn from(inner: DeviceInfo) -> Self {
-        Self { inner }
+    fn from(info: DeviceInfo) -> Self {
+        Self {
+            kind: DeviceKind::Hid(info),
+        }
     }
 }
 
 impl fmt::Display for Device {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{:04x}:{:04x}",
-            self.inner.vendor_id(),
-            self.inner.product_id()
-        )
+        match &self.kind {
+            DeviceKind::Hid(info) => {
+                write!(f, "{:04x}:{:04x}", info.vendor_id(), info.product_id())
+            }
+            DeviceKind::Replay(session) => write!(f, "replay ({:?})", session.mode()),
+            DeviceKind::Tcp(address) => write!(f, "tcp {address}"),
+        }
+    }
+}
+
+/// Whether the device is locked, as last reported by the status word of its answers
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LockState {
+    #[default]
+    Unknown,
+    Locked,
+    Unlocked,
+}
+
+/// Ledger device models, as identified by their USB product id
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceModel {
+    Blue,
+    NanoS,
+    NanoX,
+    NanoSPlus,
+    Stax,
+    Unknown,
+}
+
+impl DeviceModel {
+    /// Legacy firmwares report the model as the whole product id, newer ones in its upper byte
+    pub fn from_product_id(product_id: u16) -> Self {
+        let id = if product_id > 0xff {
+            product_id >> 8
+        } else {
+            product_id
+        };
+
+        match id {
+            0x00 => Self::Blue,
+            0x01 | 0x10 => Self::NanoS,
+            0x04 | 0x40 => Self::NanoX,
+            0x05 | 0x50 => Self::NanoSPlus,
+            0x06 | 0x60 => Self::Stax,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Size in bytes of the flash memory blocks apps are counted in
+    pub fn block_size(&self) -> Option<u32> {
+        match self {
+            Self::Blue | Self::NanoS | Self::NanoX => Some(4 * 1024),
+            Self::NanoSPlus | Self::Stax => Some(32),
+            Self::Unknown => None,
+        }
+    }
+
+    /// Whether the model runs on a battery, and so answers `GetBatteryState`
+    pub fn has_battery(&self) -> bool {
+        matches!(self, Self::NanoX | Self::Stax)
+    }
+}
+
+impl fmt::Display for DeviceModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Blue => "Ledger Blue",
+            Self::NanoS => "Nano S",
+            Self::NanoX => "Nano X",
+            Self::NanoSPlus => "Nano S Plus",
+            Self::Stax => "Stax",
+            Self::Unknown => "Unknown model",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Device targeted by commands issued from the UI. The first scanned device gets selected by default.
+#[derive(Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelectedDevice(pub Option<Entity>);
+
+/// App running on the device, as last reported by `GetAppAndVersion`
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct RunningApp {
+    pub name: String,
+    pub version: String,
+}
+
+impl RunningApp {
+    /// Decode `format | name length | name | version length | version | flags length | flags`
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Self> {
+        let (&format, rest) = data.split_first()?;
+        if format != 0x01 {
+            return None;
+        }
+
+        let (&len, rest) = rest.split_first()?;
+        let name = rest.get(..len as usize)?;
+        let (&len, rest) = rest[len as usize..].split_first()?;
+        let version = rest.get(..len as usize)?;
+
+        Some(Self {
+            name: String::from_utf8_lossy(name).into_owned(),
+            version: String::from_utf8_lossy(version).into_owned(),
+        })
+    }
+
+    /// Whether the device is on its dashboard rather than in an app
+    pub fn is_dashboard(&self) -> bool {
+        matches!(self.name.as_str(), "BOLOS" | "OLOS\0")
     }
 }
+
+/// Versions of the device's firmware, as last reported by `GetVersion`
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceVersion {
+    /// Identifies the model and its hardware revision
+    pub target_id: u32,
+    /// Version of the secure element firmware, e.g. `1.1.0`
+    pub se_version: String,
+    pub flags: Vec<u8>,
+    /// Version of the MCU firmware
+    pub mcu_version: String,
+}
+
+impl DeviceVersion {
+    /// Decode `target id | se version length | se version | flags length | flags | mcu version length | mcu version`
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Self> {
+        let target_id = u32::from_be_bytes(data.get(..4)?.try_into().ok()?);
+        let (se_version, rest) = split_prefixed(&data[4..])?;
+        let (flags, rest) = split_prefixed(rest)?;
+        let (mcu_version, _) = split_prefixed(rest)?;
+
+        Some(Self {
+            target_id,
+            se_version: String::from_utf8_lossy(se_version).into_owned(),
+            flags: flags.to_vec(),
+            mcu_version: String::from_utf8_lossy(mcu_version)
+                .trim_end_matches('\0')
+                .to_string(),
+        })
+    }
+}
+
+/// Name given to the device by the user, as last reported by `GetDeviceName`
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceName(pub String);
+
+/// Charge of the battery, as last reported by `GetBatteryState`
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BatteryState {
+    pub percentage: u8,
+}
+
+impl BatteryState {
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Self> {
+        match data {
+            [percentage] => Some(Self {
+                percentage: *percentage,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Apps installed on the device, as last listed by `ListApps`
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstalledApps(pub Vec<InstalledApp>);
+
+/// App installed on a device, as listed by `ListApps`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledApp {
+    pub name: String,
+    /// Size of the app, in blocks of the device's flash memory
+    pub blocks: u16,
+    pub flags: u16,
+    /// Hash of the app's code
+    pub hash_code_data: [u8; 32],
+    /// Hash of the whole app, as referenced by the manager API
+    pub hash: [u8; 32],
+}
+
+impl InstalledApp {
+    /// Decode a chunk of the list: `format | (length | blocks | flags | code hash | hash | name length | name)*`
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Vec<Self>> {
+        let (&format, mut rest) = data.split_first()?;
+        if format != 0x01 {
+            return None;
+        }
+
+        let mut apps = Vec::new();
+        while !rest.is_empty() {
+            let (entry, next) = split_prefixed(rest)?;
+            let blocks = u16::from_be_bytes(entry.get(..2)?.try_into().ok()?);
+            let flags = u16::from_be_bytes(entry.get(2..4)?.try_into().ok()?);
+            let hash_code_data = entry.get(4..36)?.try_into().ok()?;
+            let hash = entry.get(36..68)?.try_into().ok()?;
+            let (name, _) = split_prefixed(entry.get(68..)?)?;
+
+            apps.push(Self {
+                name: String::from_utf8_lossy(name).into_owned(),
+                blocks,
+                flags,
+                hash_code_data,
+                hash,
+            });
+            rest = next;
+        }
+
+        Some(apps)
+    }
+}
+
+/// Split a length prefixed field off the front of `data`
+fn split_prefixed(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&len, rest) = data.split_first()?;
+    Some((rest.get(..len as usize)?, &rest[len as usize..]))
+}
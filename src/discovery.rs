@@ -0,0 +1,35 @@
+use crate::{app::LedgerApp, path::DerivationPath};
+use bevy::ecs::{component::Component, entity::Entity};
+
+/// A [LedgerApp] whose accounts can be found by a [DiscoveryPlugin](crate::DiscoveryPlugin), see [DiscoverAccounts](crate::event::discovery::DiscoverAccounts)
+pub trait DiscoverableApp: LedgerApp {
+    /// Command getting the address of an account, without displaying it
+    fn address_command(path: DerivationPath) -> Self::Command;
+
+    /// Address identifying the account in the response to [address_command](Self::address_command)
+    fn address(response: &Self::Response) -> Option<String>;
+}
+
+/// Account found on a device by a discovery, spawned as its own entity
+#[derive(Component, Clone, Debug)]
+pub struct DiscoveredAccount {
+    pub device_id: Entity,
+    /// Name of the app which derived the account
+    pub app: &'static str,
+    /// Account index substituted in the path template
+    pub index: u32,
+    pub path: DerivationPath,
+    /// Address of the account, or its extended public key for Bitcoin
+    pub address: String,
+}
+
+/// Whether a [DiscoveredAccount] holds funds or has a history, to be set by the app after looking it up on chain.
+///
+/// The discovery keeps scanning until it finds `gap_limit` accounts past the last used one, and only finishes once every discovered account got a known activity or the `activity_timeout` of [DiscoverAccounts](crate::event::discovery::DiscoverAccounts) elapsed.
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AccountActivity {
+    #[default]
+    Unknown,
+    Used,
+    Unused,
+}
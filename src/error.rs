@@ -8,6 +8,9 @@ pub enum DeviceHIDError {
     /// Communication error
     #[error("Ledger device: communication error `{0}`")]
     Comm(&'static str),
+    /// The device didn't answer in time
+    #[error("Ledger device: timed out waiting for an answer")]
+    Timeout,
     /// i/o error
     #[error("Ledger device: i/o error")]
     Io(#[from] std::io::Error),
@@ -19,6 +22,13 @@ pub enum DeviceHIDError {
     UTF8(#[from] std::str::Utf8Error),
 }
 
+impl DeviceHIDError {
+    /// Whether retrying the exchange might succeed, e.g. when the device was momentarily busy
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Io(_) | Self::Hid(_))
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u16)]
 /// A list of known error values that the device can send back:
@@ -65,6 +75,8 @@ pub enum APDUErrorCode {
     LockedDevice = 0x5515,
     /// No more space in the memory to add something. It can occur when adding an app, or a language pack, or an locked screen image.
     NotEnoughSpace = 0x5102,
+    /// The user refused the operation on the device
+    UserRefusedOnDevice = 0x5501,
 
     // From ledger-rs
     /// Success
@@ -81,10 +93,121 @@ pub enum APDUErrorCode {
     SignVerifyError = 0x6F01,
 }
 
+impl TryFrom<u16> for APDUErrorCode {
+    type Error = ();
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        let code = match value {
+            0x63c0 => Self::PinRemainingAttempts,
+            0x6700 => Self::IncorrectLength,
+            0x6800 => Self::MissingCriticalParameter,
+            0x6981 => Self::CommandIncompatibleFileStructure,
+            0x6982 => Self::SecurityStatusNotSatisfied,
+            0x6985 => Self::ConditionsOfUseNotSatisfied,
+            0x6a80 => Self::IncorrectData,
+            0x6a84 => Self::NotEnoughMoneySpace,
+            0x6a88 => Self::ReferencedDataNotFound,
+            0x6a89 => Self::FileAlreadyExists,
+            0x6b00 => Self::IncorrectP1P2,
+            0x6d00 => Self::InsNotSupported,
+            0x6e00 => Self::ClaNotSupported,
+            0x6f00 => Self::TechnicalProblem,
+            0x9240 => Self::MemoryProblem,
+            0x9400 => Self::NoEFSelected,
+            0x9402 => Self::InvalidOffset,
+            0x9404 => Self::FileNotFound,
+            0x9408 => Self::InconsistentFile,
+            0x9484 => Self::AlgorithmNotSupported,
+            0x9485 => Self::InvalidKCV,
+            0x9802 => Self::CodeNotInitialized,
+            0x9804 => Self::AccessConditionNotFulfilled,
+            0x9808 => Self::ContradictionSecretCodeStatus,
+            0x9810 => Self::ContradictionInvalidation,
+            0x9840 => Self::CodeBlocked,
+            0x9850 => Self::MaxValueReached,
+            0x6300 => Self::GPAuthFailed,
+            0x6f42 => Self::Licensing,
+            0x6faa => Self::Halted,
+            0x5515 => Self::LockedDevice,
+            0x5102 => Self::NotEnoughSpace,
+            0x5501 => Self::UserRefusedOnDevice,
+            0x9000 => Self::NoError,
+            0x6400 => Self::ExecutionError,
+            0x6983 => Self::OutputBufferTooSmall,
+            0x6984 => Self::DataInvalid,
+            0x6986 => Self::CommandNotAllowed,
+            0x6F01 => Self::SignVerifyError,
+            _ => return Err(()),
+        };
+
+        Ok(code)
+    }
+}
+
+impl APDUErrorCode {
+    /// Human readable explanation of the status word, e.g. to show to users
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::PinRemainingAttempts => "wrong PIN, some attempts remain",
+            Self::IncorrectLength => "the command has an incorrect length",
+            Self::MissingCriticalParameter => "the command is missing a critical parameter",
+            Self::CommandIncompatibleFileStructure => {
+                "the command is incompatible with the file structure"
+            }
+            Self::SecurityStatusNotSatisfied => "the device is locked or access was denied",
+            Self::ConditionsOfUseNotSatisfied => "the operation was denied",
+            Self::IncorrectData => "the command data is invalid",
+            Self::NotEnoughMoneySpace => "not enough space on the device",
+            Self::ReferencedDataNotFound => "the referenced data was not found",
+            Self::FileAlreadyExists => "the file already exists",
+            Self::IncorrectP1P2 => "the command parameters are invalid",
+            Self::InsNotSupported => "the command is not supported by the running app",
+            Self::ClaNotSupported => "the command class is not supported by the running app",
+            Self::TechnicalProblem => "technical problem on the device",
+            Self::MemoryProblem => "memory problem on the device",
+            Self::NoEFSelected => "no file selected",
+            Self::InvalidOffset => "invalid offset",
+            Self::FileNotFound => "file not found",
+            Self::InconsistentFile => "inconsistent file",
+            Self::AlgorithmNotSupported => "algorithm not supported",
+            Self::InvalidKCV => "invalid key check value",
+            Self::CodeNotInitialized => "code not initialized",
+            Self::AccessConditionNotFulfilled => "access condition not fulfilled",
+            Self::ContradictionSecretCodeStatus => "contradiction with the secret code status",
+            Self::ContradictionInvalidation => "contradiction with the invalidation",
+            Self::CodeBlocked => "code blocked",
+            Self::MaxValueReached => "maximum value reached",
+            Self::GPAuthFailed => "secure channel authentication failed",
+            Self::Licensing => "licensing error",
+            Self::Halted => "the device is halted",
+            Self::LockedDevice => "the device is locked",
+            Self::NotEnoughSpace => "not enough space left on the device",
+            Self::UserRefusedOnDevice => "the user refused on the device",
+            Self::NoError => "success",
+            Self::ExecutionError => "the command failed to execute",
+            Self::OutputBufferTooSmall => "the answer buffer is too small",
+            Self::DataInvalid => "the command parameters are invalid",
+            Self::CommandNotAllowed => "the command is not allowed",
+            Self::SignVerifyError => "signature verification failed",
+        }
+    }
+
+    /// Whether the user refused the operation on the device
+    pub fn is_user_rejection(self) -> bool {
+        matches!(
+            self,
+            Self::UserRefusedOnDevice | Self::ConditionsOfUseNotSatisfied
+        )
+    }
+}
+
 #[derive(thiserror::Error, Debug, PartialEq, Eq)]
 /// Error interpreting bytes as an APDU answer
 pub enum APDUAnswerError {
     #[error("answer too short (< 2 bytes)")]
     /// Passed APDU answer was less than the minimum 2 bytes required for the return code
     TooShort,
+    #[error("malformed answer: {0}")]
+    /// The answer's payload doesn't follow the format expected for the command
+    Malformed(&'static str),
 }
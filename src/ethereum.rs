@@ -0,0 +1,250 @@
+use crate::path::DerivationPath;
+use bevy::ecs::{component::Component, system::Resource};
+use serde::{Deserialize, Deserializer};
+use std::{fs, path::Path};
+
+/// Configuration of the Ethereum app, inserted on the device entity once received
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EthAppConfiguration {
+    /// Whether the user allowed signing contract data the app cannot decode
+    pub blind_signing_enabled: bool,
+    /// Whether ERC-20 token info must be provided before signing token transfers
+    pub erc20_info_needed: bool,
+    pub version: (u8, u8, u8),
+}
+
+impl EthAppConfiguration {
+    /// Decode `flags | major | minor | patch`
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Self> {
+        let [flags, major, minor, patch] = *data.get(..4)? else {
+            return None;
+        };
+
+        Some(Self {
+            blind_signing_enabled: flags & 0x01 != 0,
+            erc20_info_needed: flags & 0x02 != 0,
+            version: (major, minor, patch),
+        })
+    }
+}
+
+/// Signed metadata fed to the Ethereum app before signing so that it can clear sign transactions, usually loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "erc20": [{ "ticker": "USDC", "address": "0xa0b8…", "decimals": 6, "chain_id": 1, "signature": "3045…" }],
+///   "plugins": [{ "name": "Paraswap", "address": "0xdef1…", "selector": "0xa9059cbb", "signature": "3045…" }],
+///   "nfts": [{ "name": "Cryptopunks", "address": "0xb47e…", "chain_id": 1, "signature": "3045…" }]
+/// }
+/// ```
+#[derive(Resource, Deserialize, Clone, Debug, Default)]
+pub struct EthDescriptors {
+    #[serde(default)]
+    pub erc20: Vec<Erc20Descriptor>,
+    #[serde(default)]
+    pub plugins: Vec<ExternalPluginDescriptor>,
+    #[serde(default)]
+    pub nfts: Vec<NftDescriptor>,
+}
+
+impl EthDescriptors {
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn erc20(&self, chain_id: u64, address: &[u8; 20]) -> Option<&Erc20Descriptor> {
+        self.erc20
+            .iter()
+            .find(|t| t.chain_id as u64 == chain_id && &t.address == address)
+    }
+
+    pub fn plugin(
+        &self,
+        address: &[u8; 20],
+        selector: &[u8; 4],
+    ) -> Option<&ExternalPluginDescriptor> {
+        self.plugins
+            .iter()
+            .find(|p| &p.address == address && &p.selector == selector)
+    }
+
+    pub fn nft(&self, chain_id: u64, address: &[u8; 20]) -> Option<&NftDescriptor> {
+        self.nfts
+            .iter()
+            .find(|n| n.chain_id == chain_id && &n.address == address)
+    }
+}
+
+/// ERC-20 token metadata signed by Ledger
+#[derive(Deserialize, Clone, Debug)]
+pub struct Erc20Descriptor {
+    pub ticker: String,
+    #[serde(deserialize_with = "hex_array")]
+    pub address: [u8; 20],
+    pub decimals: u32,
+    pub chain_id: u32,
+    #[serde(deserialize_with = "hex_vec")]
+    pub signature: Vec<u8>,
+}
+
+impl Erc20Descriptor {
+    /// `ticker length | ticker | address | decimals | chain id | signature`
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        let mut data = vec![self.ticker.len() as u8];
+        data.extend(self.ticker.as_bytes());
+        data.extend(self.address);
+        data.extend(self.decimals.to_be_bytes());
+        data.extend(self.chain_id.to_be_bytes());
+        data.extend(&self.signature);
+        data
+    }
+}
+
+/// External plugin of the Ethereum app able to decode calls to a contract method, signed by Ledger
+#[derive(Deserialize, Clone, Debug)]
+pub struct ExternalPluginDescriptor {
+    pub name: String,
+    #[serde(deserialize_with = "hex_array")]
+    pub address: [u8; 20],
+    #[serde(deserialize_with = "hex_array")]
+    pub selector: [u8; 4],
+    #[serde(deserialize_with = "hex_vec")]
+    pub signature: Vec<u8>,
+}
+
+impl ExternalPluginDescriptor {
+    /// `name length | name | address | selector | signature`
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        let mut data = vec![self.name.len() as u8];
+        data.extend(self.name.as_bytes());
+        data.extend(self.address);
+        data.extend(self.selector);
+        data.extend(&self.signature);
+        data
+    }
+}
+
+/// NFT collection metadata signed by Ledger
+#[derive(Deserialize, Clone, Debug)]
+pub struct NftDescriptor {
+    pub name: String,
+    #[serde(deserialize_with = "hex_array")]
+    pub address: [u8; 20],
+    pub chain_id: u64,
+    /// Id of the Ledger key which signed the descriptor
+    #[serde(default = "NftDescriptor::production_key")]
+    pub key_id: u8,
+    /// Id of the signature algorithm, ECDSA with SHA-256 by default
+    #[serde(default = "NftDescriptor::ecdsa_sha256")]
+    pub algorithm_id: u8,
+    #[serde(deserialize_with = "hex_vec")]
+    pub signature: Vec<u8>,
+}
+
+impl NftDescriptor {
+    fn production_key() -> u8 {
+        0x01
+    }
+
+    fn ecdsa_sha256() -> u8 {
+        0x01
+    }
+
+    /// `type | version | name length | name | address | chain id | key id | algorithm id | signature length | signature`
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        let mut data = vec![0x01, 0x01, self.name.len() as u8];
+        data.extend(self.name.as_bytes());
+        data.extend(self.address);
+        data.extend(self.chain_id.to_be_bytes());
+        data.extend([self.key_id, self.algorithm_id, self.signature.len() as u8]);
+        data.extend(&self.signature);
+        data
+    }
+}
+
+fn hex_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    hex::decode(hex.strip_prefix("0x").unwrap_or(&hex)).map_err(serde::de::Error::custom)
+}
+
+fn hex_array<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[u8; N], D::Error> {
+    hex_vec(deserializer)?
+        .try_into()
+        .map_err(|_| serde::de::Error::custom(format!("expected {N} bytes")))
+}
+
+/// The Ethereum app. Send it [AppCommand](crate::event::app::AppCommand)`<Ethereum>`s and read the [AppResponse](crate::event::app::AppResponse)`<Ethereum>`s, the app gets opened first if needed.
+pub struct Ethereum;
+
+#[derive(Clone, Debug)]
+pub enum EthCommand {
+    /// Get the public key and address of a BIP-32 path
+    GetAddress {
+        path: DerivationPath,
+        /// Display the address on the device and wait for the user to confirm it
+        display: bool,
+        /// Also return the chain code of the path
+        chain_code: bool,
+    },
+    /// Sign a raw RLP encoded transaction, either legacy or EIP-2718 typed (EIP-2930, EIP-1559). The user has to approve the transaction.
+    ///
+    /// The metadata of [EthDescriptors] matching the transaction is provided to the app first, so that it can clear sign it.
+    SignTransaction {
+        path: DerivationPath,
+        /// Unsigned transaction, prefixed by its type when typed
+        raw_tx: Vec<u8>,
+    },
+    /// Sign a message following EIP-191 `personal_sign`, the user has to approve the message
+    SignPersonalMessage {
+        path: DerivationPath,
+        message: Vec<u8>,
+    },
+    /// Sign EIP-712 typed data from its domain separator and message hashes. The device can only show the hashes to the user.
+    SignEip712Hashed {
+        path: DerivationPath,
+        domain_hash: [u8; 32],
+        message_hash: [u8; 32],
+    },
+    /// Sign EIP-712 typed data by streaming its struct definitions and values to the device, which shows every field to the user
+    SignEip712Message {
+        path: DerivationPath,
+        /// Typed data as passed to `eth_signTypedData_v4`, with `types`, `primaryType`, `domain` and `message`
+        typed_data: serde_json::Value,
+    },
+    /// Get the version of the app and its settings, e.g. whether blind signing is enabled. The configuration also gets inserted on the device entity.
+    GetAppConfiguration,
+    /// Provide ERC-20 token metadata so that the app shows token transfers with their ticker and decimals
+    ProvideErc20TokenInfo(Erc20Descriptor),
+    /// Select the external plugin decoding calls to a contract method
+    SetExternalPlugin(ExternalPluginDescriptor),
+    /// Provide NFT collection metadata
+    ProvideNftInfo(NftDescriptor),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EthResponse {
+    Address {
+        /// Uncompressed secp256k1 public key
+        public_key: Vec<u8>,
+        /// `0x` prefixed, checksummed address
+        address: String,
+        chain_code: Option<[u8; 32]>,
+    },
+    /// Answer to every signing command
+    Signature(EthSignature),
+    AppConfiguration(EthAppConfiguration),
+    /// The app accepted the provided metadata
+    MetadataProvided,
+}
+
+/// ECDSA signature
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EthSignature {
+    /// Recovery id, including the EIP-155 chain id for legacy transactions, 27 or 28 for messages
+    pub v: u64,
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+}
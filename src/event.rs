@@ -1 +1,5 @@
+pub mod app;
+pub mod discovery;
 pub mod general;
+pub mod lock;
+pub mod queue;
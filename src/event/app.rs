@@ -0,0 +1,19 @@
+use crate::{
+    app::{AppError, LedgerApp},
+    queue::CommandId,
+};
+use bevy::ecs::entity::Entity;
+
+/// Send a command of the app `A`, opening it first if needed
+pub struct AppCommand<A: LedgerApp> {
+    pub device_id: Entity,
+    pub command: A::Command,
+}
+
+/// Answer to [AppCommand], decoded by the app
+pub struct AppResponse<A: LedgerApp> {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub command: A::Command,
+    pub result: Result<A::Response, AppError>,
+}
@@ -0,0 +1,42 @@
+use crate::path::PathTemplate;
+use bevy::ecs::entity::Entity;
+use std::time::Duration;
+
+/// Derive the accounts of a path template one index after the other, opening the app first if needed.
+///
+/// The discovery relies on the app to look the accounts up on chain and set their [AccountActivity](crate::discovery::AccountActivity): it only ends once every account got one, or its `activity_timeout` elapsed.
+///
+/// Accounts found by a previous discovery of the same app on the device get despawned.
+pub struct DiscoverAccounts {
+    pub device_id: Entity,
+    /// [Name](crate::app::LedgerApp::NAME) of a [DiscoverableApp](crate::discovery::DiscoverableApp), e.g. `Ethereum::NAME`
+    pub app: &'static str,
+    pub template: PathTemplate,
+    /// Number of consecutive unused accounts to scan past the last used one
+    pub gap_limit: u32,
+    /// Delay after which an account whose activity is still unknown is taken as unused. Without it, the discovery waits for every activity.
+    pub activity_timeout: Option<Duration>,
+}
+
+/// Stop the discovery running on a device, keeping the accounts found so far
+pub struct CancelDiscovery {
+    pub device_id: Entity,
+}
+
+/// How a discovery ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiscoveryOutcome {
+    /// Every account within the gap limit got scanned
+    Completed,
+    Cancelled,
+    Failed(String),
+}
+
+/// Emitted once a discovery ended
+pub struct DiscoveryFinished {
+    pub device_id: Entity,
+    pub app: &'static str,
+    /// [DiscoveredAccount](crate::discovery::DiscoveredAccount) entities, by account index
+    pub accounts: Vec<Entity>,
+    pub outcome: DiscoveryOutcome,
+}
@@ -1,30 +1,81 @@
+use crate::{
+    apdu::{APDUAnswer, APDUCommand},
+    device::{BatteryState, DeviceVersion, InstalledApp, RunningApp},
+    queue::CommandId,
+};
 use bevy::ecs::entity::Entity;
 
 /// Event for scanning Ledger devices connected via HID
 pub struct ScanDevices;
 
+/// Emitted once a [ScanDevices] got handled. The devices found get spawned by the end of the frame.
+pub struct DevicesScanned;
+
 /// Get device information including the versions of its components, the onboarding status and its current state.
 pub struct GetVersion {
     pub device_id: Entity,
 }
 
+/// Answer to [GetVersion]. The version also gets inserted on the device entity.
+pub struct VersionReceived {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub version: DeviceVersion,
+}
+
 /// Get information on the application currently running on the device. When no application is running on the device we will get BOLOS (or OLOS for very old firmware versions) meaning the device is currently on the dashboard.
-pub struct GetAppAndVersion;
+pub struct GetAppAndVersion {
+    pub device_id: Entity,
+}
+
+/// Answer to [GetAppAndVersion]. The app also gets inserted on the device entity.
+pub struct AppAndVersionReceived {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub app: RunningApp,
+}
+
+/// List all the applications installed on a device alongside their hashes. The user has to allow the manager on the device.
+pub struct ListApps {
+    pub device_id: Entity,
+}
 
-/// List all the applications installed on a device alongside their versions.
-pub struct ListApps;
+/// Answer to [ListApps]. The apps also get inserted on the device entity.
+pub struct AppsListed {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub apps: Vec<InstalledApp>,
+}
 
 /// Prompt to open an application by name as seen on the manager api endpoint. Note that the successful execution of this command will also trigger a disconnect meaning we will get the response following by a connection loss and a reconnection (on USB at least) when the device lands on target app.
 pub struct OpenApp {
     pub device_id: Entity,
-    pub name: &'static str,
+    pub name: String,
 }
 
 /// Quit the application currently running on the connected device. Note that the successful execution of this command will also trigger a disconnect meaning we will get the response following by a connection loss and a reconnection (on USB at least) when the device lands on the dashboard (BOLOS app)
-pub struct QuitApp;
+pub struct QuitApp {
+    pub device_id: Entity,
+}
+
+/// Emitted when the app required by queued commands couldn't be opened. Those commands get cancelled.
+pub struct AppOpenFailed {
+    pub device_id: Entity,
+    pub app: &'static str,
+    pub reason: String,
+}
 
 /// Get the device name, this is a user blocking APDU where the user can refuse the operation.
-pub struct GetDeviceName;
+pub struct GetDeviceName {
+    pub device_id: Entity,
+}
+
+/// Answer to [GetDeviceName]. The name also gets inserted on the device entity.
+pub struct DeviceNameReceived {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub name: String,
+}
 
 ///
 pub struct EditDeviceName;
@@ -33,4 +84,34 @@ pub struct UninstallLanguage;
 
 pub struct StaxFetchImageSize;
 
-pub struct GetBatteryState;
+/// Get the charge of the battery, on devices having one
+pub struct GetBatteryState {
+    pub device_id: Entity,
+}
+
+/// Answer to [GetBatteryState]. The state also gets inserted on the device entity.
+pub struct BatteryStateReceived {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub state: BatteryState,
+}
+
+/// Send an arbitrary APDU, e.g. to explore an app's commands
+pub struct SendRawApdu {
+    pub device_id: Entity,
+    pub apdu: APDUCommand<Vec<u8>>,
+}
+
+/// Emitted when the device successfully answered one of the commands above, but the answer cannot be decoded, e.g. because of an unsupported firmware
+pub struct AnswerMalformed {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub name: &'static str,
+}
+
+/// Answer to [SendRawApdu], whatever its status word, or why the exchange failed
+pub struct RawApduAnswered {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub result: Result<APDUAnswer<Vec<u8>>, String>,
+}
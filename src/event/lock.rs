@@ -0,0 +1,11 @@
+use bevy::ecs::entity::Entity;
+
+/// Emitted when a device reports being locked. Commands will fail until the user enters their PIN.
+pub struct DeviceLocked {
+    pub device_id: Entity,
+}
+
+/// Emitted when a device reports being unlocked
+pub struct DeviceUnlocked {
+    pub device_id: Entity,
+}
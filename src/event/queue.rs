@@ -0,0 +1,51 @@
+use crate::{apdu::APDUAnswer, queue::CommandId};
+use bevy::ecs::entity::Entity;
+use std::time::Duration;
+
+/// Cancel a command which is still waiting in the device's queue. Commands already sent to the device cannot be cancelled.
+pub struct CancelCommand {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+}
+
+/// Emitted once a queued command got cancelled before being sent
+pub struct CommandCancelled {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub name: &'static str,
+}
+
+/// Emitted once the device answered a queued command, or the exchange failed
+pub struct CommandCompleted {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub name: &'static str,
+    pub result: eyre::Result<APDUAnswer<Vec<u8>>>,
+    /// Timeout applied to each attempt
+    pub timeout: Duration,
+    /// Number of exchanges made, more than one when the command got retried
+    pub attempts: u32,
+}
+
+/// Stage reached by a queued command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandStage {
+    /// The command got sent to the device
+    Sent,
+    /// The device is waiting for the user to approve or reject the command
+    AwaitingUserConfirmation,
+    /// The user approved the command on the device
+    UserApproved,
+    /// The user rejected the command on the device
+    UserRejected,
+    /// The device didn't answer before the timeout
+    TimedOut,
+}
+
+/// Emitted whenever a queued command reaches a new [CommandStage], e.g. to show a "Confirm on your device" overlay
+pub struct CommandLifecycle {
+    pub device_id: Entity,
+    pub command_id: CommandId,
+    pub name: &'static str,
+    pub stage: CommandStage,
+}
@@ -1,9 +1,19 @@
 pub mod apdu;
+pub mod app;
+pub mod bitcoin;
+pub mod config;
 mod constant;
-mod device;
+pub mod device;
+pub mod discovery;
 pub mod error;
+pub mod ethereum;
 pub mod event;
+pub mod path;
 mod plugin;
+pub mod queue;
+pub mod record;
+pub mod replay;
+pub mod solana;
 mod transport;
 pub mod ui;
 
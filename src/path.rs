@@ -0,0 +1,285 @@
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// Offset of hardened components
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Maximum number of components Ledger apps accept
+pub const MAX_DEPTH: usize = 10;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DerivationPathError {
+    #[error("derivation path: invalid component `{0}`")]
+    InvalidComponent(String),
+    #[error("derivation path: component `{0}` is out of range")]
+    OutOfRange(String),
+    #[error("derivation path: more than {MAX_DEPTH} components")]
+    TooDeep,
+    #[error("path template: missing `{ACCOUNT_PLACEHOLDER}` placeholder")]
+    MissingPlaceholder,
+}
+
+/// Placeholder replaced by the account index in a [PathTemplate]
+pub const ACCOUNT_PLACEHOLDER: &str = "{account}";
+
+/// BIP-32 derivation path, e.g. `m/44'/60'/0'/0/0`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Path from raw components, hardened ones being offset by [HARDENED]
+    pub fn new(components: Vec<u32>) -> Result<Self, DerivationPathError> {
+        if components.len() > MAX_DEPTH {
+            return Err(DerivationPathError::TooDeep);
+        }
+
+        Ok(Self(components))
+    }
+
+    pub fn components(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether every component is hardened, as required for ed25519 keys
+    pub fn is_fully_hardened(&self) -> bool {
+        self.0.iter().all(|c| c & HARDENED != 0)
+    }
+
+    /// Path with one more component
+    pub fn child(&self, component: u32) -> Result<Self, DerivationPathError> {
+        Self::new([&self.0[..], &[component]].concat())
+    }
+
+    /// Ledger wire format: `count | components`, components as big endian `u32`
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(1 + self.0.len() * 4);
+        v.push(self.0.len() as u8);
+        self.0.iter().for_each(|c| v.extend(c.to_be_bytes()));
+        v
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = DerivationPathError;
+
+    /// Parse `m/44'/60'/0'/0/0`, the `m/` prefix being optional and `h` or `H` also marking hardened components
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix("m/")
+            .or_else(|| s.strip_prefix("M/"))
+            .unwrap_or(s);
+        if s.is_empty() || s == "m" || s == "M" {
+            return Ok(Self::default());
+        }
+
+        let components = s
+            .split('/')
+            .map(|c| {
+                let (index, hardened) = match c.strip_suffix(['\'', 'h', 'H']) {
+                    Some(index) => (index, true),
+                    None => (c, false),
+                };
+                if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(DerivationPathError::InvalidComponent(c.to_string()));
+                }
+
+                let index: u32 = index
+                    .parse()
+                    .map_err(|_| DerivationPathError::OutOfRange(c.to_string()))?;
+                if index >= HARDENED {
+                    return Err(DerivationPathError::OutOfRange(c.to_string()));
+                }
+
+                Ok(if hardened { index | HARDENED } else { index })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Self::new(components)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        self.0.iter().try_for_each(|c| {
+            if c & HARDENED != 0 {
+                write!(f, "/{}'", c & !HARDENED)
+            } else {
+                write!(f, "/{c}")
+            }
+        })
+    }
+}
+
+impl TryFrom<Vec<u32>> for DerivationPath {
+    type Error = DerivationPathError;
+
+    fn try_from(components: Vec<u32>) -> Result<Self, Self::Error> {
+        Self::new(components)
+    }
+}
+
+/// Derivation path with an account placeholder, e.g. `m/44'/60'/{account}'/0/0`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PathTemplate(String);
+
+impl PathTemplate {
+    /// Path of the given account
+    pub fn path(&self, account: u32) -> Result<DerivationPath, DerivationPathError> {
+        self.0
+            .replace(ACCOUNT_PLACEHOLDER, &account.to_string())
+            .parse()
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = DerivationPathError;
+
+    /// Parse a template, checking the path of the first account is valid
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(ACCOUNT_PLACEHOLDER) {
+            return Err(DerivationPathError::MissingPlaceholder);
+        }
+
+        let template = Self(s.trim().to_string());
+        template.path(0)?;
+        Ok(template)
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(s: &str) -> Result<DerivationPath, DerivationPathError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_bip44_path() {
+        let eth = path("m/44'/60'/0'/0/0").unwrap();
+        assert_eq!(
+            eth.components(),
+            [44 | HARDENED, 60 | HARDENED, HARDENED, 0, 0]
+        );
+        assert_eq!(eth.depth(), 5);
+        assert!(!eth.is_fully_hardened());
+    }
+
+    #[test]
+    fn hardened_markers_and_prefix() {
+        let expected = path("m/44'/501'/0'").unwrap();
+        assert!(expected.is_fully_hardened());
+
+        [
+            "m/44h/501h/0h",
+            "m/44H/501H/0H",
+            "M/44'/501h/0H",
+            "44'/501'/0'",
+            " 44h/501'/0' ",
+        ]
+        .into_iter()
+        .for_each(|s| assert_eq!(path(s), Ok(expected.clone()), "{s}"));
+
+        assert_eq!(path("m"), Ok(DerivationPath::default()));
+        assert_eq!(path(""), Ok(DerivationPath::default()));
+    }
+
+    #[test]
+    fn rejects_invalid_components() {
+        ["m/44'/x", "m/44'//0", "m/'", "m/-1", "m/44''", "m/+1"]
+            .into_iter()
+            .for_each(|s| {
+                assert!(
+                    matches!(path(s), Err(DerivationPathError::InvalidComponent(_))),
+                    "{s}"
+                )
+            });
+    }
+
+    #[test]
+    fn rejects_out_of_range_indexes() {
+        assert_eq!(
+            path("m/2147483647'").map(|p| p.components().to_vec()),
+            Ok(vec![u32::MAX])
+        );
+        assert_eq!(
+            path("m/2147483648"),
+            Err(DerivationPathError::OutOfRange("2147483648".to_string()))
+        );
+        assert_eq!(
+            path("m/2147483648'"),
+            Err(DerivationPathError::OutOfRange("2147483648'".to_string()))
+        );
+        assert_eq!(
+            path("m/4294967296"),
+            Err(DerivationPathError::OutOfRange("4294967296".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_deep_paths() {
+        let max = ["0"; MAX_DEPTH].join("/");
+        assert_eq!(path(&max).map(|p| p.depth()), Ok(MAX_DEPTH));
+        assert_eq!(path(&format!("{max}/0")), Err(DerivationPathError::TooDeep));
+
+        let deepest = path(&max).unwrap();
+        assert_eq!(deepest.child(0), Err(DerivationPathError::TooDeep));
+    }
+
+    #[test]
+    fn serializes_to_wire_format() {
+        let serialized = path("m/44'/60'/0'/0/1").unwrap().serialize();
+        assert_eq!(
+            serialized,
+            [5, 0x80, 0, 0, 44, 0x80, 0, 0, 60, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(DerivationPath::default().serialize(), [0]);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        [
+            "m",
+            "m/44'/60'/0'/0/0",
+            "m/84'/1'/0'/1/7",
+            "m/2147483647'/0",
+        ]
+        .into_iter()
+        .for_each(|s| assert_eq!(path(s).unwrap().to_string(), s));
+
+        assert_eq!(path("44h/0H/1").unwrap().to_string(), "m/44'/0'/1");
+    }
+
+    #[test]
+    fn template_substitutes_account() {
+        let template: PathTemplate = "m/44'/60'/{account}'/0/0".parse().unwrap();
+        assert_eq!(template.path(0), path("m/44'/60'/0'/0/0"));
+        assert_eq!(template.path(7), path("m/44'/60'/7'/0/0"));
+        assert!(template.path(HARDENED).is_err());
+        assert_eq!(template.to_string(), "m/44'/60'/{account}'/0/0");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert_eq!(
+            "m/44'/60'/0'/0/0".parse::<PathTemplate>(),
+            Err(DerivationPathError::MissingPlaceholder)
+        );
+        assert!(matches!(
+            "m/44'/x/{account}'".parse::<PathTemplate>(),
+            Err(DerivationPathError::InvalidComponent(_))
+        ));
+    }
+}
@@ -1,12 +1,36 @@
+mod app;
+mod bitcoin;
+mod discovery;
+mod ethereum;
 mod general;
+mod launcher;
+mod lock;
+mod queue;
+mod solana;
 
 use bevy::app::{PluginGroup, PluginGroupBuilder};
+use bitcoin::BitcoinPlugin;
+use ethereum::EthereumPlugin;
 use general::GeneralPlugin;
+use launcher::LauncherPlugin;
+use lock::LockPlugin;
+use queue::QueuePlugin;
+use solana::SolanaPlugin;
+
+pub use app::LedgerAppPlugin;
+pub use discovery::DiscoveryPlugin;
 
 pub struct LedgerPlugins;
 
 impl PluginGroup for LedgerPlugins {
     fn build(self) -> bevy::app::PluginGroupBuilder {
-        PluginGroupBuilder::start::<Self>().add(GeneralPlugin)
+        PluginGroupBuilder::start::<Self>()
+            .add(QueuePlugin)
+            .add(GeneralPlugin)
+            .add(LockPlugin)
+            .add(LauncherPlugin)
+            .add(EthereumPlugin)
+            .add(BitcoinPlugin)
+            .add(SolanaPlugin)
     }
 }
@@ -0,0 +1,228 @@
+use crate::{
+    app::{AppError, LedgerApp},
+    error::APDUErrorCode,
+    event::{
+        app::*,
+        queue::{CommandCancelled, CommandCompleted},
+    },
+    queue::{CommandId, CommandQueue},
+};
+use bevy::{log, prelude::*, utils::HashMap};
+use std::marker::PhantomData;
+
+/// Wires the events of a [LedgerApp]: every [AppCommand] goes through the device's queue and gets answered by an [AppResponse]
+pub struct LedgerAppPlugin<A>(PhantomData<A>);
+
+impl<A> Default for LedgerAppPlugin<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<A: LedgerApp> Plugin for LedgerAppPlugin<A> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingAppCommands<A>>()
+            .add_event::<AppCommand<A>>()
+            .add_event::<AppResponse<A>>()
+            .add_systems((send_app_commands::<A>, on_app_answers::<A>));
+    }
+}
+
+/// Commands of the app waiting for an answer
+#[derive(Resource)]
+pub(super) struct PendingAppCommands<A: LedgerApp>(HashMap<CommandId, (A::Command, A::State)>);
+
+impl<A: LedgerApp> PendingAppCommands<A> {
+    /// Queue a command, its answer getting emitted as an [AppResponse]
+    pub fn push(
+        &mut self,
+        queue: &mut CommandQueue,
+        command: A::Command,
+    ) -> eyre::Result<CommandId> {
+        let (queued, state) = A::queued(&command)?;
+        let id = queue.push(queued.for_app(A::NAME));
+        self.0.insert(id, (command, state));
+        Ok(id)
+    }
+}
+
+impl<A: LedgerApp> Default for PendingAppCommands<A> {
+    fn default() -> Self {
+        Self(HashMap::default())
+    }
+}
+
+pub(super) fn send_app_commands<A: LedgerApp>(
+    mut events: EventReader<AppCommand<A>>,
+    mut queues: Query<&mut CommandQueue>,
+    mut pending: ResMut<PendingAppCommands<A>>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot send {} command: unknown device", A::NAME);
+            return;
+        };
+
+        if let Err(err) = pending.push(&mut queue, e.command.clone()) {
+            log::error!("Cannot send {} command: {err}", A::NAME);
+        }
+    });
+}
+
+fn on_app_answers<A: LedgerApp>(
+    mut completed: EventReader<CommandCompleted>,
+    mut cancelled: EventReader<CommandCancelled>,
+    mut pending: ResMut<PendingAppCommands<A>>,
+    mut responses: EventWriter<AppResponse<A>>,
+) {
+    cancelled.iter().for_each(|e| {
+        pending.0.remove(&e.command_id);
+    });
+
+    completed.iter().for_each(|e| {
+        let Some((command, state)) = pending.0.remove(&e.command_id) else {
+            return;
+        };
+
+        let result = match &e.result {
+            Ok(answer) => match answer.error_code() {
+                Ok(APDUErrorCode::NoError) => {
+                    A::decode(&command, state, answer.data()).map_err(AppError::from)
+                }
+                Ok(code) if code.is_user_rejection() => Err(AppError::Rejected),
+                Ok(APDUErrorCode::ClaNotSupported) => Err(AppError::NotOpen(A::NAME)),
+                _ => Err(AppError::Status(answer.retcode())),
+            },
+            Err(err) => Err(AppError::Exchange(err.to_string())),
+        };
+
+        if let Err(err) = &result {
+            log::error!("{} {}: {err}", e.name, e.command_id);
+        }
+
+        responses.send(AppResponse {
+            device_id: e.device_id,
+            command_id: e.command_id,
+            command,
+            result,
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apdu::APDUAnswer,
+        solana::{SolAppConfiguration, SolCommand, SolResponse, Solana},
+    };
+    use std::time::Duration;
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_event::<CommandCompleted>()
+            .add_event::<CommandCancelled>()
+            .add_plugin(LedgerAppPlugin::<Solana>::default());
+        let device = app.world.spawn(CommandQueue::default()).id();
+        (app, device)
+    }
+
+    /// Send `command`, returning the id it got queued with
+    fn send(app: &mut App, device_id: Entity, command: SolCommand) -> Option<CommandId> {
+        app.world
+            .send_event(AppCommand::<Solana> { device_id, command });
+        app.update();
+
+        let queue = app.world.get::<CommandQueue>(device_id).unwrap();
+        queue.pending().last().map(|c| c.id)
+    }
+
+    fn answer(app: &mut App, device_id: Entity, command_id: CommandId, answer: Vec<u8>) {
+        app.world.send_event(CommandCompleted {
+            device_id,
+            command_id,
+            name: "Test",
+            result: APDUAnswer::from_answer(answer),
+            timeout: Duration::from_secs(1),
+            attempts: 1,
+        });
+        app.update();
+    }
+
+    /// Responses emitted by the last update
+    fn responses(app: &App) -> Vec<&Result<SolResponse, AppError>> {
+        let events = app.world.resource::<Events<AppResponse<Solana>>>();
+        events
+            .iter_current_update_events()
+            .map(|e| &e.result)
+            .collect()
+    }
+
+    #[test]
+    fn decodes_successful_answers() {
+        let (mut app, device) = app();
+        let id = send(&mut app, device, SolCommand::GetAppConfiguration).unwrap();
+        answer(&mut app, device, id, vec![0x01, 0x00, 1, 2, 3, 0x90, 0x00]);
+
+        let configuration = SolAppConfiguration {
+            blind_signing_enabled: true,
+            long_pubkey_display: true,
+            version: (1, 2, 3),
+        };
+        assert!(matches!(
+            responses(&app)[..],
+            [Ok(SolResponse::AppConfiguration(c))] if *c == configuration
+        ));
+    }
+
+    #[test]
+    fn maps_status_words_to_errors() {
+        let (mut app, device) = app();
+        let mut answered = |data: Vec<u8>| {
+            let id = send(&mut app, device, SolCommand::GetAppConfiguration).unwrap();
+            answer(&mut app, device, id, data);
+            format!("{:?}", responses(&app))
+        };
+
+        assert_eq!(answered(vec![0x69, 0x85]), "[Err(Rejected)]");
+        assert_eq!(answered(vec![0x6e, 0x00]), "[Err(NotOpen(\"Solana\"))]");
+        assert_eq!(answered(vec![0x6a, 0x80]), "[Err(Status(27264))]");
+        assert!(answered(vec![0x01, 0x90, 0x00]).starts_with("[Err(Malformed("));
+    }
+
+    #[test]
+    fn drops_invalid_commands() {
+        let (mut app, device) = app();
+        let command = SolCommand::GetPubkey {
+            path: "m/44'/0".parse().unwrap(),
+            display: false,
+        };
+
+        assert_eq!(send(&mut app, device, command), None);
+        assert!(app
+            .world
+            .resource::<PendingAppCommands<Solana>>()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn forgets_cancelled_commands() {
+        let (mut app, device) = app();
+        let id = send(&mut app, device, SolCommand::GetAppConfiguration).unwrap();
+        app.world.send_event(CommandCancelled {
+            device_id: device,
+            command_id: id,
+            name: "Test",
+        });
+        app.update();
+
+        assert!(app
+            .world
+            .resource::<PendingAppCommands<Solana>>()
+            .0
+            .is_empty());
+        answer(&mut app, device, id, vec![0x01, 0x00, 1, 2, 3, 0x90, 0x00]);
+        assert!(responses(&app).is_empty());
+    }
+}
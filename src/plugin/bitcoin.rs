@@ -0,0 +1,269 @@
+mod interpreter;
+mod merkle;
+
+use super::{DiscoveryPlugin, LedgerAppPlugin};
+use crate::{
+    apdu::APDUCommand,
+    app::LedgerApp,
+    bitcoin::{
+        read_compact_size, write_compact_size, Bitcoin, BtcCommand, BtcPartialSignature,
+        BtcResponse,
+    },
+    constant::*,
+    discovery::DiscoverableApp,
+    error::APDUAnswerError,
+    path::DerivationPath,
+    queue::QueuedCommand,
+};
+use bevy::prelude::*;
+use interpreter::{ClientCommandInterpreter, Yielded};
+use merkle::sha256;
+
+const GET_BTC_MASTER_FINGERPRINT: &str = "GetBtcMasterFingerprint";
+const GET_BTC_EXTENDED_PUBKEY: &str = "GetBtcExtendedPubkey";
+const REGISTER_BTC_WALLET: &str = "RegisterBtcWallet";
+const GET_BTC_WALLET_ADDRESS: &str = "GetBtcWalletAddress";
+const SIGN_BTC_PSBT: &str = "SignBtcPsbt";
+
+pub struct BitcoinPlugin;
+
+impl Plugin for BitcoinPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(LedgerAppPlugin::<Bitcoin>::default())
+            .add_plugin(DiscoveryPlugin::<Bitcoin>::default());
+    }
+}
+
+impl LedgerApp for Bitcoin {
+    const NAME: &'static str = "Bitcoin";
+    const CLA: u8 = CLA_BTC;
+    type Command = BtcCommand;
+    type Response = BtcResponse;
+    /// What the device yielded while signing a PSBT, i.e. the signatures
+    type State = Option<Yielded>;
+
+    fn queued(command: &BtcCommand) -> eyre::Result<(QueuedCommand, Self::State)> {
+        let mut interpreter = ClientCommandInterpreter::default();
+        let command = match command {
+            BtcCommand::GetMasterFingerprint => QueuedCommand::new(
+                GET_BTC_MASTER_FINGERPRINT,
+                btc_apdu(INS_BTC_GET_MASTER_FINGERPRINT, Vec::new()),
+            )
+            .idempotent(),
+            BtcCommand::GetExtendedPubkey { path, display } => {
+                let mut data = vec![u8::from(*display)];
+                data.extend(path.serialize());
+
+                let command = QueuedCommand::new(
+                    GET_BTC_EXTENDED_PUBKEY,
+                    btc_apdu(INS_BTC_GET_EXTENDED_PUBKEY, data),
+                );
+                match display {
+                    true => command.with_user_confirmation(),
+                    false => command.idempotent(),
+                }
+            }
+            BtcCommand::RegisterWallet(policy) => {
+                let policy = interpreter.add_wallet_policy(policy);
+                let mut data = write_compact_size(policy.len() as u64);
+                data.extend(policy);
+
+                QueuedCommand::new(REGISTER_BTC_WALLET, btc_apdu(INS_BTC_REGISTER_WALLET, data))
+                    .with_user_confirmation()
+                    .with_interactive(interpreter)
+            }
+            BtcCommand::GetWalletAddress {
+                policy,
+                hmac,
+                change,
+                address_index,
+                display,
+            } => {
+                let policy = interpreter.add_wallet_policy(policy);
+
+                // `display | wallet id | hmac | change | address index`
+                let mut data = vec![u8::from(*display)];
+                data.extend(sha256(&policy));
+                data.extend(hmac.unwrap_or_default());
+                data.push(u8::from(*change));
+                data.extend(address_index.to_be_bytes());
+
+                let mut command = QueuedCommand::new(
+                    GET_BTC_WALLET_ADDRESS,
+                    btc_apdu(INS_BTC_GET_WALLET_ADDRESS, data),
+                );
+                if *display {
+                    command = command.with_user_confirmation();
+                }
+                command.with_interactive(interpreter)
+            }
+            BtcCommand::SignPsbt { psbt, policy, hmac } => {
+                let policy = interpreter.add_wallet_policy(policy);
+                let global = interpreter.add_known_mapping(&psbt.global);
+                let inputs: Vec<_> = psbt
+                    .inputs
+                    .iter()
+                    .map(|m| interpreter.add_known_mapping(m))
+                    .collect();
+                let outputs: Vec<_> = psbt
+                    .outputs
+                    .iter()
+                    .map(|m| interpreter.add_known_mapping(m))
+                    .collect();
+
+                // `global commitment | inputs count | inputs root | outputs count | outputs root | wallet id | hmac`
+                let mut data = global;
+                data.extend(write_compact_size(inputs.len() as u64));
+                data.extend(interpreter.add_known_list(&inputs));
+                data.extend(write_compact_size(outputs.len() as u64));
+                data.extend(interpreter.add_known_list(&outputs));
+                data.extend(sha256(&policy));
+                data.extend(hmac.unwrap_or_default());
+
+                let yielded = interpreter.yielded();
+                let command = QueuedCommand::new(SIGN_BTC_PSBT, btc_apdu(INS_BTC_SIGN_PSBT, data))
+                    .with_user_confirmation()
+                    .with_interactive(interpreter);
+                return Ok((command, Some(yielded)));
+            }
+        };
+
+        Ok((command, None))
+    }
+
+    fn decode(
+        command: &BtcCommand,
+        yielded: Option<Yielded>,
+        data: &[u8],
+    ) -> Result<BtcResponse, APDUAnswerError> {
+        match command {
+            BtcCommand::GetMasterFingerprint => <[u8; 4]>::try_from(data)
+                .map(BtcResponse::MasterFingerprint)
+                .map_err(|_| APDUAnswerError::Malformed("fingerprint is not 4 bytes long")),
+            BtcCommand::GetExtendedPubkey { .. } => std::str::from_utf8(data)
+                .map(|xpub| BtcResponse::ExtendedPubkey(xpub.to_string()))
+                .map_err(|_| APDUAnswerError::Malformed("extended public key is not ASCII")),
+            BtcCommand::RegisterWallet(_) => match data.len() {
+                64 => Ok(BtcResponse::WalletRegistered {
+                    wallet_id: *arrayref::array_ref!(data, 0, 32),
+                    hmac: *arrayref::array_ref!(data, 32, 32),
+                }),
+                _ => Err(APDUAnswerError::Malformed(
+                    "registration is not 64 bytes long",
+                )),
+            },
+            BtcCommand::GetWalletAddress { .. } => std::str::from_utf8(data)
+                .map(|address| BtcResponse::WalletAddress(address.to_string()))
+                .map_err(|_| APDUAnswerError::Malformed("address is not ASCII")),
+            BtcCommand::SignPsbt { .. } => {
+                let yielded = yielded.unwrap_or_default();
+                let yielded = yielded.lock().unwrap();
+                yielded
+                    .iter()
+                    .map(|y| decode_partial_signature(y))
+                    .collect::<Result<_, _>>()
+                    .map(BtcResponse::PsbtSigned)
+            }
+        }
+    }
+}
+
+impl DiscoverableApp for Bitcoin {
+    fn address_command(path: DerivationPath) -> BtcCommand {
+        BtcCommand::GetExtendedPubkey {
+            path,
+            display: false,
+        }
+    }
+
+    fn address(response: &BtcResponse) -> Option<String> {
+        match response {
+            BtcResponse::ExtendedPubkey(xpub) => Some(xpub.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn btc_apdu(ins: u8, data: Vec<u8>) -> APDUCommand<Vec<u8>> {
+    Bitcoin::apdu(ins, 0x00, BTC_PROTOCOL_VERSION, data)
+}
+
+/// Decode `input index | public key length | public key | leaf hash? | signature`
+fn decode_partial_signature(mut data: &[u8]) -> Result<BtcPartialSignature, APDUAnswerError> {
+    let input_index = read_compact_size(&mut data)
+        .map_err(|_| APDUAnswerError::Malformed("missing input index"))?
+        as usize;
+    let (len, rest) = data
+        .split_first()
+        .ok_or(APDUAnswerError::Malformed("missing public key length"))?;
+    if rest.len() < *len as usize {
+        return Err(APDUAnswerError::Malformed(
+            "public key shorter than its length",
+        ));
+    }
+    let (augmented, signature) = rest.split_at(*len as usize);
+
+    let (pubkey, tapleaf_hash) = match augmented.len() {
+        32 | 33 => (augmented, None),
+        64 => (
+            &augmented[..32],
+            Some(*arrayref::array_ref!(augmented, 32, 32)),
+        ),
+        _ => return Err(APDUAnswerError::Malformed("unexpected public key length")),
+    };
+
+    Ok(BtcPartialSignature {
+        input_index,
+        pubkey: pubkey.to_vec(),
+        tapleaf_hash,
+        signature: signature.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_ecdsa_signature() {
+        let pubkey = [0x02; 33];
+        let der = [0x30, 0x44, 0x02, 0x20, 0x01];
+        let data = [&[0x01, 33][..], &pubkey, &der].concat();
+
+        assert_eq!(
+            decode_partial_signature(&data),
+            Ok(BtcPartialSignature {
+                input_index: 1,
+                pubkey: pubkey.to_vec(),
+                tapleaf_hash: None,
+                signature: der.to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_taproot_signatures() {
+        let key_path = [&[0xfd, 0x2c, 0x01, 32][..], &[0x03; 32], &[0xaa; 64]].concat();
+        let signature = decode_partial_signature(&key_path).unwrap();
+        assert_eq!(signature.input_index, 300);
+        assert_eq!(signature.pubkey, [0x03; 32]);
+        assert_eq!(signature.tapleaf_hash, None);
+        assert_eq!(signature.signature, [0xaa; 64]);
+
+        // The leaf hash follows the x-only key when spending through a script
+        let script_path = [&[0x00, 64][..], &[0x03; 32], &[0x04; 32], &[0xbb; 65]].concat();
+        let signature = decode_partial_signature(&script_path).unwrap();
+        assert_eq!(signature.input_index, 0);
+        assert_eq!(signature.pubkey, [0x03; 32]);
+        assert_eq!(signature.tapleaf_hash, Some([0x04; 32]));
+        assert_eq!(signature.signature, [0xbb; 65]);
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        assert!(decode_partial_signature(&[]).is_err());
+        assert!(decode_partial_signature(&[0x00]).is_err());
+        assert!(decode_partial_signature(&[0x00, 33, 0x02]).is_err());
+        assert!(decode_partial_signature(&[&[0x00, 20][..], &[0x02; 20]].concat()).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! Answers to the client commands the Bitcoin app sends while processing a command, to fetch data it only got committed to
+
+use super::merkle::{element_hash, sha256, Hash, MerkleTree};
+use crate::{
+    apdu::{APDUAnswer, APDUCommand},
+    bitcoin::{read_compact_size, write_compact_size, PsbtMap, WalletPolicy},
+    constant::{CLA_BTC_FRAMEWORK, INS_BTC_CONTINUE_INTERRUPTED, SW_BTC_INTERRUPTED_EXECUTION},
+    queue::Interactive,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+
+const YIELD: u8 = 0x10;
+const GET_PREIMAGE: u8 = 0x40;
+const GET_MERKLE_LEAF_PROOF: u8 = 0x41;
+const GET_MERKLE_LEAF_INDEX: u8 = 0x42;
+const GET_MORE_ELEMENTS: u8 = 0xa0;
+
+/// Maximum payload of an APDU
+const MAX_APDU_DATA: usize = 255;
+
+const WALLET_POLICY_V2: u8 = 0x02;
+
+#[derive(Error, Debug)]
+pub enum ClientCommandError {
+    #[error("Bitcoin client command: empty request")]
+    Empty,
+    #[error("Bitcoin client command: unknown command {0:#04x}")]
+    Unknown(u8),
+    #[error("Bitcoin client command: malformed request {0:#04x}")]
+    Malformed(u8),
+    #[error("Bitcoin client command: unknown preimage {0}")]
+    UnknownPreimage(String),
+    #[error("Bitcoin client command: unknown merkle tree {0}")]
+    UnknownTree(String),
+    #[error("Bitcoin client command: no more elements queued")]
+    NoMoreElements,
+}
+
+/// Payloads the device yielded while processing the command, e.g. the signatures of a PSBT
+pub type Yielded = Arc<Mutex<Vec<Vec<u8>>>>;
+
+#[derive(Default)]
+pub struct ClientCommandInterpreter {
+    preimages: HashMap<Hash, Vec<u8>>,
+    trees: HashMap<Hash, MerkleTree>,
+    /// Elements which didn't fit in the previous answer, fetched with `GET_MORE_ELEMENTS`
+    queue: VecDeque<Vec<u8>>,
+    yielded: Yielded,
+}
+
+impl ClientCommandInterpreter {
+    pub fn yielded(&self) -> Yielded {
+        self.yielded.clone()
+    }
+
+    pub fn add_known_preimage(&mut self, preimage: Vec<u8>) {
+        self.preimages.insert(sha256(&preimage), preimage);
+    }
+
+    /// Make the elements and the merkle tree committing to them available, returning its root
+    pub fn add_known_list(&mut self, elements: &[impl AsRef<[u8]>]) -> Hash {
+        let leaves = elements
+            .iter()
+            .map(|e| {
+                let e = e.as_ref();
+                self.add_known_preimage([&[0x00], e].concat());
+                element_hash(e)
+            })
+            .collect();
+
+        let tree = MerkleTree::new(leaves);
+        let root = tree.root();
+        self.trees.insert(root, tree);
+        root
+    }
+
+    /// Make a map available, returning its commitment `size | keys root | values root`
+    pub fn add_known_mapping(&mut self, map: &PsbtMap) -> Vec<u8> {
+        let keys: Vec<_> = map.keys().collect();
+        let values: Vec<_> = map.values().collect();
+
+        let mut commitment = write_compact_size(map.len() as u64);
+        commitment.extend(self.add_known_list(&keys));
+        commitment.extend(self.add_known_list(&values));
+        commitment
+    }
+
+    /// Make a wallet policy and its keys available, returning its serialization
+    pub fn add_wallet_policy(&mut self, policy: &WalletPolicy) -> Vec<u8> {
+        let keys: Vec<_> = policy.keys_info.iter().map(String::as_bytes).collect();
+        let keys_root = self.add_known_list(&keys);
+        let template = policy.descriptor_template.as_bytes();
+        self.add_known_preimage(template.to_vec());
+
+        // `version | name length | name | template length | template hash | keys count | keys root`
+        let mut serialized = vec![WALLET_POLICY_V2, policy.name.len() as u8];
+        serialized.extend(policy.name.as_bytes());
+        serialized.extend(write_compact_size(template.len() as u64));
+        serialized.extend(sha256(template));
+        serialized.extend(write_compact_size(keys.len() as u64));
+        serialized.extend(keys_root);
+
+        self.add_known_preimage(serialized.clone());
+        serialized
+    }
+
+    fn execute(&mut self, request: &[u8]) -> Result<Vec<u8>, ClientCommandError> {
+        let (&code, req) = request.split_first().ok_or(ClientCommandError::Empty)?;
+
+        match code {
+            YIELD => {
+                self.yielded.lock().unwrap().push(req.to_vec());
+                Ok(Vec::new())
+            }
+            GET_PREIMAGE => self.get_preimage(req),
+            GET_MERKLE_LEAF_PROOF => self.get_merkle_leaf_proof(req),
+            GET_MERKLE_LEAF_INDEX => self.get_merkle_leaf_index(req),
+            GET_MORE_ELEMENTS => self.get_more_elements(),
+            _ => Err(ClientCommandError::Unknown(code)),
+        }
+    }
+
+    /// `0x00 | hash` → `preimage length | byte count | bytes`, the rest being queued byte by byte
+    fn get_preimage(&mut self, req: &[u8]) -> Result<Vec<u8>, ClientCommandError> {
+        let [0x00, hash @ ..] = req else {
+            return Err(ClientCommandError::Malformed(GET_PREIMAGE));
+        };
+        let hash: Hash = hash
+            .try_into()
+            .map_err(|_| ClientCommandError::Malformed(GET_PREIMAGE))?;
+        let preimage = self
+            .preimages
+            .get(&hash)
+            .ok_or_else(|| ClientCommandError::UnknownPreimage(hex::encode(hash)))?;
+
+        let mut response = write_compact_size(preimage.len() as u64);
+        let size = (MAX_APDU_DATA - response.len() - 1).min(preimage.len());
+        response.push(size as u8);
+        response.extend(&preimage[..size]);
+        self.queue
+            .extend(preimage[size..].iter().map(|byte| vec![*byte]));
+        Ok(response)
+    }
+
+    /// `root | tree size | leaf index` → `leaf | proof length | proof count | proof`, the rest of the proof being queued
+    fn get_merkle_leaf_proof(&mut self, req: &[u8]) -> Result<Vec<u8>, ClientCommandError> {
+        let (root, tree_size, index) = split_hash(req)
+            .and_then(|(root, mut rest)| {
+                let tree_size = read_compact_size(&mut rest).ok()?;
+                let index = read_compact_size(&mut rest).ok()?;
+                Some((root, tree_size, index as usize))
+            })
+            .ok_or(ClientCommandError::Malformed(GET_MERKLE_LEAF_PROOF))?;
+
+        let tree = self
+            .trees
+            .get(&root)
+            .ok_or_else(|| ClientCommandError::UnknownTree(hex::encode(root)))?;
+        let leaf = tree
+            .leaf(index)
+            .filter(|_| tree.size() as u64 == tree_size)
+            .ok_or(ClientCommandError::Malformed(GET_MERKLE_LEAF_PROOF))?;
+
+        let proof = tree.prove_leaf(index);
+        let count = ((MAX_APDU_DATA - 32 - 2) / 32).min(proof.len());
+
+        let mut response = leaf.to_vec();
+        response.extend([proof.len() as u8, count as u8]);
+        proof[..count].iter().for_each(|h| response.extend(h));
+        self.queue.extend(proof[count..].iter().map(|h| h.to_vec()));
+        Ok(response)
+    }
+
+    /// `root | leaf` → `found | leaf index`
+    fn get_merkle_leaf_index(&mut self, req: &[u8]) -> Result<Vec<u8>, ClientCommandError> {
+        let (root, leaf) = split_hash(req)
+            .and_then(|(root, rest)| Some((root, split_hash(rest)?)))
+            .filter(|(_, (_, rest))| rest.is_empty())
+            .map(|(root, (leaf, _))| (root, leaf))
+            .ok_or(ClientCommandError::Malformed(GET_MERKLE_LEAF_INDEX))?;
+
+        let tree = self
+            .trees
+            .get(&root)
+            .ok_or_else(|| ClientCommandError::UnknownTree(hex::encode(root)))?;
+
+        let (found, index) = match tree.leaf_index(&leaf) {
+            Some(index) => (1, index),
+            None => (0, 0),
+        };
+        Ok([vec![found], write_compact_size(index as u64)].concat())
+    }
+
+    /// `count | element length | elements`, as many queued elements as fit
+    fn get_more_elements(&mut self) -> Result<Vec<u8>, ClientCommandError> {
+        let len = self
+            .queue
+            .front()
+            .ok_or(ClientCommandError::NoMoreElements)?
+            .len();
+        if self.queue.iter().any(|e| e.len() != len) {
+            return Err(ClientCommandError::Malformed(GET_MORE_ELEMENTS));
+        }
+
+        let count = ((MAX_APDU_DATA - 2) / len.max(1)).min(self.queue.len());
+        let mut response = vec![count as u8, len as u8];
+        self.queue.drain(..count).for_each(|e| response.extend(e));
+        Ok(response)
+    }
+}
+
+impl Interactive for ClientCommandInterpreter {
+    fn respond(
+        &mut self,
+        answer: &APDUAnswer<Vec<u8>>,
+    ) -> eyre::Result<Option<APDUCommand<Vec<u8>>>> {
+        if answer.retcode() != SW_BTC_INTERRUPTED_EXECUTION {
+            return Ok(None);
+        }
+
+        Ok(Some(APDUCommand {
+            cla: CLA_BTC_FRAMEWORK,
+            ins: INS_BTC_CONTINUE_INTERRUPTED,
+            p1: 0x00,
+            p2: 0x00,
+            data: self.execute(answer.data())?,
+        }))
+    }
+}
+
+fn split_hash(data: &[u8]) -> Option<(Hash, &[u8])> {
+    let hash = data.get(..32)?.try_into().ok()?;
+    Some((hash, &data[32..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answer interrupting the command with the given client command
+    fn request(request: &[u8]) -> APDUAnswer<Vec<u8>> {
+        APDUAnswer::from_answer([request, &SW_BTC_INTERRUPTED_EXECUTION.to_be_bytes()].concat())
+            .unwrap()
+    }
+
+    /// Data of the `CONTINUE_INTERRUPTED` APDU answering `req`
+    fn respond(interpreter: &mut ClientCommandInterpreter, req: &[u8]) -> Vec<u8> {
+        let apdu = interpreter.respond(&request(req)).unwrap().unwrap();
+        assert_eq!(
+            (apdu.cla, apdu.ins, apdu.p1, apdu.p2),
+            (CLA_BTC_FRAMEWORK, INS_BTC_CONTINUE_INTERRUPTED, 0x00, 0x00)
+        );
+        apdu.data
+    }
+
+    fn get_more_elements(interpreter: &mut ClientCommandInterpreter) -> Vec<u8> {
+        respond(interpreter, &[GET_MORE_ELEMENTS])
+    }
+
+    #[test]
+    fn completed_command_needs_no_response() {
+        let mut interpreter = ClientCommandInterpreter::default();
+        let answer = APDUAnswer::from_answer(vec![0x01, 0x90, 0x00]).unwrap();
+        assert!(interpreter.respond(&answer).unwrap().is_none());
+
+        let rejected = APDUAnswer::from_answer(vec![0x69, 0x85]).unwrap();
+        assert!(interpreter.respond(&rejected).unwrap().is_none());
+    }
+
+    #[test]
+    fn get_preimage() {
+        let mut interpreter = ClientCommandInterpreter::default();
+        interpreter.add_known_preimage(b"abc".to_vec());
+
+        let req = [&[GET_PREIMAGE, 0x00][..], &sha256(b"abc")].concat();
+        assert_eq!(respond(&mut interpreter, &req), [3, 3, b'a', b'b', b'c']);
+
+        let unknown = [&[GET_PREIMAGE, 0x00][..], &[0xaa; 32]].concat();
+        assert!(interpreter.respond(&request(&unknown)).is_err());
+        let malformed = [&[GET_PREIMAGE, 0x01][..], &sha256(b"abc")].concat();
+        assert!(interpreter.respond(&request(&malformed)).is_err());
+    }
+
+    #[test]
+    fn long_preimage_continues_with_get_more_elements() {
+        let preimage: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        let mut interpreter = ClientCommandInterpreter::default();
+        interpreter.add_known_preimage(preimage.clone());
+
+        // `0xfd 0x58 0x02` is 600 as a compact size, leaving room for 251 bytes
+        let req = [&[GET_PREIMAGE, 0x00][..], &sha256(&preimage)].concat();
+        let response = respond(&mut interpreter, &req);
+        assert_eq!(response.len(), MAX_APDU_DATA);
+        assert_eq!(response[..4], [0xfd, 0x58, 0x02, 251]);
+        assert_eq!(response[4..], preimage[..251]);
+
+        // The 349 remaining bytes are queued as single byte elements
+        let response = get_more_elements(&mut interpreter);
+        assert_eq!(response.len(), MAX_APDU_DATA);
+        assert_eq!(response[..2], [253, 1]);
+        assert_eq!(response[2..], preimage[251..504]);
+
+        let response = get_more_elements(&mut interpreter);
+        assert_eq!(response[..2], [96, 1]);
+        assert_eq!(response[2..], preimage[504..]);
+
+        assert!(interpreter.respond(&request(&[GET_MORE_ELEMENTS])).is_err());
+    }
+
+    #[test]
+    fn get_merkle_leaf_proof() {
+        let elements: Vec<_> = (0..200u8).map(|i| vec![i]).collect();
+        let mut interpreter = ClientCommandInterpreter::default();
+        let root = interpreter.add_known_list(&elements);
+        let tree = MerkleTree::new(elements.iter().map(|e| element_hash(e)).collect());
+        let proof = tree.prove_leaf(42);
+        assert_eq!(proof.len(), 8);
+
+        // `root | tree size | leaf index`, 200 fitting a single byte compact size
+        let req = [&[GET_MERKLE_LEAF_PROOF][..], &root, &[200, 42]].concat();
+        let response = respond(&mut interpreter, &req);
+        assert_eq!(response[..32], element_hash(&[42]));
+        assert_eq!(response[32..34], [8, 6]);
+        assert_eq!(response[34..], proof[..6].concat());
+
+        let response = get_more_elements(&mut interpreter);
+        assert_eq!(response[..2], [2, 32]);
+        assert_eq!(response[2..], proof[6..].concat());
+
+        let wrong_size = [&[GET_MERKLE_LEAF_PROOF][..], &root, &[199, 42]].concat();
+        assert!(interpreter.respond(&request(&wrong_size)).is_err());
+        let unknown = [&[GET_MERKLE_LEAF_PROOF][..], &[0xaa; 32], &[200, 42]].concat();
+        assert!(interpreter.respond(&request(&unknown)).is_err());
+    }
+
+    #[test]
+    fn get_merkle_leaf_index() {
+        let mut interpreter = ClientCommandInterpreter::default();
+        let root = interpreter.add_known_list(&[b"a", b"b", b"c"]);
+
+        let req = [&[GET_MERKLE_LEAF_INDEX][..], &root, &element_hash(b"c")].concat();
+        assert_eq!(respond(&mut interpreter, &req), [1, 2]);
+
+        let req = [&[GET_MERKLE_LEAF_INDEX][..], &root, &element_hash(b"d")].concat();
+        assert_eq!(respond(&mut interpreter, &req), [0, 0]);
+
+        let trailing = [
+            &[GET_MERKLE_LEAF_INDEX][..],
+            &root,
+            &element_hash(b"c"),
+            &[0],
+        ]
+        .concat();
+        assert!(interpreter.respond(&request(&trailing)).is_err());
+    }
+
+    #[test]
+    fn yield_collects_payloads() {
+        let mut interpreter = ClientCommandInterpreter::default();
+        let yielded = interpreter.yielded();
+
+        assert!(respond(&mut interpreter, &[YIELD, 0x01, 0x02]).is_empty());
+        assert!(respond(&mut interpreter, &[YIELD, 0x03]).is_empty());
+        assert_eq!(*yielded.lock().unwrap(), [vec![0x01, 0x02], vec![0x03]]);
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        let mut interpreter = ClientCommandInterpreter::default();
+        assert!(matches!(
+            interpreter.execute(&[]),
+            Err(ClientCommandError::Empty)
+        ));
+        assert!(matches!(
+            interpreter.execute(&[0x99]),
+            Err(ClientCommandError::Unknown(0x99))
+        ));
+    }
+
+    #[test]
+    fn serializes_wallet_policy() {
+        let policy = WalletPolicy {
+            name: String::new(),
+            descriptor_template: "wpkh(@0/**)".into(),
+            keys_info: vec!["[f5acc2fd/84'/1'/0']tpubDC".into()],
+        };
+        let mut interpreter = ClientCommandInterpreter::default();
+        let serialized = interpreter.add_wallet_policy(&policy);
+
+        let keys_root = MerkleTree::new(vec![element_hash(policy.keys_info[0].as_bytes())]).root();
+        let expected = [
+            &[WALLET_POLICY_V2, 0, 11][..],
+            &sha256(b"wpkh(@0/**)"),
+            &[1],
+            &keys_root,
+        ]
+        .concat();
+        assert_eq!(serialized, expected);
+
+        // The device fetches the template and the policy by their hash
+        let req = [&[GET_PREIMAGE, 0x00][..], &sha256(b"wpkh(@0/**)")].concat();
+        assert_eq!(respond(&mut interpreter, &req)[2..], *b"wpkh(@0/**)");
+        let req = [&[GET_PREIMAGE, 0x00][..], &sha256(&expected)].concat();
+        assert_eq!(respond(&mut interpreter, &req)[2..], expected);
+    }
+}
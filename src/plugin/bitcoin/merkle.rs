@@ -0,0 +1,188 @@
+//! Merkle trees as used by the Bitcoin app to commit to lists it fetches element by element
+
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+pub fn sha256(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+/// Hash of a list element, domain separated from the inner nodes
+pub fn element_hash(element: &[u8]) -> Hash {
+    sha256(&[&[0x00], element].concat())
+}
+
+fn combine_hashes(left: &Hash, right: &Hash) -> Hash {
+    sha256(&[&[0x01], &left[..], &right[..]].concat())
+}
+
+/// Size of the left subtree of a tree of `n > 1` leaves, the largest power of 2 strictly smaller than `n`
+fn left_size(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+fn root_of(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => [0; 32],
+        1 => leaves[0],
+        n => {
+            let (left, right) = leaves.split_at(left_size(n));
+            combine_hashes(&root_of(left), &root_of(right))
+        }
+    }
+}
+
+pub struct MerkleTree {
+    leaves: Vec<Hash>,
+    root: Hash,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Hash>) -> Self {
+        let root = root_of(&leaves);
+        Self { leaves, root }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+
+    pub fn size(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn leaf(&self, index: usize) -> Option<Hash> {
+        self.leaves.get(index).copied()
+    }
+
+    pub fn leaf_index(&self, leaf: &Hash) -> Option<usize> {
+        self.leaves.iter().position(|l| l == leaf)
+    }
+
+    /// Sibling hashes from the leaf up to the root
+    pub fn prove_leaf(&self, index: usize) -> Vec<Hash> {
+        fn prove(leaves: &[Hash], index: usize) -> Vec<Hash> {
+            if leaves.len() <= 1 {
+                return Vec::new();
+            }
+
+            let (left, right) = leaves.split_at(left_size(leaves.len()));
+            let (mut proof, sibling) = if index < left.len() {
+                (prove(left, index), root_of(right))
+            } else {
+                (prove(right, index - left.len()), root_of(left))
+            };
+            proof.push(sibling);
+            proof
+        }
+
+        prove(&self.leaves, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaves of the elements `[0x00]`, `[0x01]`, ...
+    fn leaves(n: u8) -> Vec<Hash> {
+        (0..n).map(|i| element_hash(&[i])).collect()
+    }
+
+    /// Verify `proof` as the Bitcoin app does, walking the tree down to the leaf then hashing back up
+    fn verify(root: Hash, size: usize, index: usize, leaf: Hash, proof: &[Hash]) -> bool {
+        let (mut size, mut index) = (size, index);
+        let mut is_left = Vec::new();
+        while size > 1 {
+            let left = left_size(size);
+            is_left.push(index < left);
+            if index < left {
+                size = left;
+            } else {
+                index -= left;
+                size -= left;
+            }
+        }
+
+        is_left.len() == proof.len()
+            && is_left.iter().rev().zip(proof).fold(
+                leaf,
+                |hash, (is_left, sibling)| match is_left {
+                    true => combine_hashes(&hash, sibling),
+                    false => combine_hashes(sibling, &hash),
+                },
+            ) == root
+    }
+
+    #[test]
+    fn subtree_sizes() {
+        assert_eq!(left_size(2), 1);
+        assert_eq!(left_size(3), 2);
+        assert_eq!(left_size(4), 2);
+        assert_eq!(left_size(5), 4);
+        assert_eq!(left_size(8), 4);
+        assert_eq!(left_size(9), 8);
+    }
+
+    #[test]
+    fn roots() {
+        // Computed bottom-up per the app's doc/merkle.md: sha256(0x00 | element) leaves, sha256(0x01 | left | right)
+        // nodes, an odd node being carried to the next level
+        let vectors = [
+            (
+                1,
+                "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            ),
+            (
+                2,
+                "a20bf9a7cc2dc8a08f5f415a71b19f6ac427bab54d24eec868b5d3103449953a",
+            ),
+            (
+                3,
+                "3b6cccd7e3e023ff393006f030315ee7ad9eb111b022b41fba7e5b7a3973f688",
+            ),
+            (
+                5,
+                "b855b42d6c30f5b087e05266783fbd6e394f7b926013ccaa67700a8b0c5a596f",
+            ),
+            (
+                8,
+                "ef7f49b620f6c7ea9b963a214da34b5021c6ded8ed57734380a311ab726aa907",
+            ),
+        ];
+
+        vectors.into_iter().for_each(|(n, root)| {
+            let tree = MerkleTree::new(leaves(n));
+            assert_eq!(hex::encode(tree.root()), root, "{n} leaves");
+        });
+        assert_eq!(MerkleTree::new(Vec::new()).root(), [0; 32]);
+    }
+
+    #[test]
+    fn proofs() {
+        (1..=9).for_each(|n| {
+            let tree = MerkleTree::new(leaves(n));
+            (0..tree.size()).for_each(|i| {
+                let leaf = tree.leaf(i).unwrap();
+                let proof = tree.prove_leaf(i);
+                assert_eq!(tree.leaf_index(&leaf), Some(i));
+                assert!(
+                    verify(tree.root(), tree.size(), i, leaf, &proof),
+                    "{i} of {n}"
+                );
+                assert!(!verify(tree.root(), tree.size(), i, [0xff; 32], &proof));
+            });
+        });
+    }
+
+    #[test]
+    fn proof_of_three_leaves() {
+        let leaves = leaves(3);
+        let tree = MerkleTree::new(leaves.clone());
+
+        // The sibling leaf, then the root of the right subtree
+        assert_eq!(tree.prove_leaf(0), [leaves[1], leaves[2]]);
+        assert_eq!(tree.prove_leaf(2), [combine_hashes(&leaves[0], &leaves[1])]);
+    }
+}
@@ -0,0 +1,479 @@
+use super::app::PendingAppCommands;
+use crate::{
+    device::Device,
+    discovery::{AccountActivity, DiscoverableApp, DiscoveredAccount},
+    event::{
+        app::AppResponse,
+        discovery::*,
+        queue::{CancelCommand, CommandCancelled},
+    },
+    path::{DerivationPath, PathTemplate},
+    queue::{CommandId, CommandQueue},
+};
+use bevy::{log, prelude::*, utils::HashMap};
+use std::{marker::PhantomData, time::Duration};
+
+/// Discovers the accounts of the app `A` on [DiscoverAccounts] events naming it. Added by the plugins of the apps shipped with the crate.
+pub struct DiscoveryPlugin<A>(PhantomData<A>);
+
+impl<A> Default for DiscoveryPlugin<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<A: DiscoverableApp> Plugin for DiscoveryPlugin<A> {
+    fn build(&self, app: &mut App) {
+        // Shared by the discovery plugins of every app
+        if !app.world.contains_resource::<Events<DiscoverAccounts>>() {
+            app.add_event::<DiscoverAccounts>()
+                .add_event::<CancelDiscovery>()
+                .add_event::<DiscoveryFinished>()
+                .add_systems((
+                    cancel_discoveries.before(end_discoveries),
+                    end_discoveries,
+                    despawn_orphan_accounts,
+                ));
+        }
+
+        // Settled discoveries end within the frame
+        app.add_systems(
+            (
+                start_discoveries::<A>,
+                on_discovered_accounts::<A>,
+                advance_discoveries::<A>,
+            )
+                .chain()
+                .before(end_discoveries),
+        );
+    }
+}
+
+/// Discovery running on a device
+#[derive(Component)]
+struct AccountDiscovery {
+    app: &'static str,
+    template: PathTemplate,
+    gap_limit: u32,
+    activity_timeout: Option<Duration>,
+    /// Next account index to request
+    next_index: u32,
+    /// Requested accounts by command, with their index and path
+    pending: HashMap<CommandId, (u32, DerivationPath)>,
+    /// Accounts found so far, with their index and when they got found
+    accounts: Vec<(u32, Entity, Duration)>,
+    /// Set once the discovery has to end
+    outcome: Option<DiscoveryOutcome>,
+}
+
+fn start_discoveries<A: DiscoverableApp>(
+    mut events: EventReader<DiscoverAccounts>,
+    devices: Query<Option<&AccountDiscovery>, With<CommandQueue>>,
+    accounts: Query<(Entity, &DiscoveredAccount)>,
+    mut commands: Commands,
+) {
+    events.iter().filter(|e| e.app == A::NAME).for_each(|e| {
+        let Ok(running) = devices.get(e.device_id) else {
+            log::error!("Cannot discover accounts: unknown device");
+            return;
+        };
+        if running.is_some() {
+            log::error!("Cannot discover accounts: a discovery is already running on the device");
+            return;
+        }
+        // Reject templates the app cannot derive before requesting anything, e.g. non hardened ed25519 paths
+        let checked = e
+            .template
+            .path(0)
+            .map_err(eyre::Report::from)
+            .and_then(|path| A::queued(&A::address_command(path)));
+        if let Err(err) = checked {
+            log::error!(
+                "Cannot discover {} accounts of {}: {err}",
+                A::NAME,
+                e.template
+            );
+            return;
+        }
+
+        log::info!("Discovering {} accounts of {}", A::NAME, e.template);
+
+        accounts
+            .iter()
+            .filter(|(_, a)| a.device_id == e.device_id && a.app == A::NAME)
+            .for_each(|(account, _)| commands.entity(account).despawn());
+
+        commands.entity(e.device_id).insert(AccountDiscovery {
+            app: A::NAME,
+            template: e.template.clone(),
+            gap_limit: e.gap_limit,
+            activity_timeout: e.activity_timeout,
+            next_index: 0,
+            pending: HashMap::default(),
+            accounts: Vec::new(),
+            outcome: None,
+        });
+    });
+}
+
+fn cancel_discoveries(
+    mut events: EventReader<CancelDiscovery>,
+    mut discoveries: Query<&mut AccountDiscovery>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut discovery) = discoveries.get_mut(e.device_id) else {
+            log::warn!("Cannot cancel discovery: none running on the device");
+            return;
+        };
+
+        discovery.outcome.get_or_insert(DiscoveryOutcome::Cancelled);
+    });
+}
+
+fn on_discovered_accounts<A: DiscoverableApp>(
+    time: Res<Time>,
+    mut responses: EventReader<AppResponse<A>>,
+    mut cancelled: EventReader<CommandCancelled>,
+    mut discoveries: Query<&mut AccountDiscovery>,
+    mut commands: Commands,
+) {
+    cancelled.iter().for_each(|e| {
+        let Ok(mut discovery) = discoveries.get_mut(e.device_id) else {
+            return;
+        };
+
+        if discovery.pending.remove(&e.command_id).is_some() {
+            let reason = "account request got cancelled".to_string();
+            discovery
+                .outcome
+                .get_or_insert(DiscoveryOutcome::Failed(reason));
+        }
+    });
+
+    responses.iter().for_each(|e| {
+        let Ok(mut discovery) = discoveries.get_mut(e.device_id) else {
+            return;
+        };
+        let Some((index, path)) = discovery.pending.remove(&e.command_id) else {
+            return;
+        };
+
+        let address = match &e.result {
+            Ok(response) => A::address(response)
+                .ok_or_else(|| "unexpected response to the address command".to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        match address {
+            Ok(address) => {
+                let account = commands
+                    .spawn((
+                        DiscoveredAccount {
+                            device_id: e.device_id,
+                            app: A::NAME,
+                            index,
+                            path,
+                            address,
+                        },
+                        AccountActivity::default(),
+                    ))
+                    .id();
+                discovery.accounts.push((index, account, time.elapsed()));
+            }
+            Err(err) => {
+                discovery
+                    .outcome
+                    .get_or_insert(DiscoveryOutcome::Failed(err));
+            }
+        }
+    });
+}
+
+/// Request accounts up to the gap limit past the last used one, and settle discoveries once every account got an activity
+fn advance_discoveries<A: DiscoverableApp>(
+    time: Res<Time>,
+    mut discoveries: Query<(&mut AccountDiscovery, &mut CommandQueue)>,
+    activities: Query<&AccountActivity>,
+    mut pending: ResMut<PendingAppCommands<A>>,
+    mut commands: Commands,
+) {
+    discoveries.for_each_mut(|(mut discovery, mut queue)| {
+        if discovery.app != A::NAME || discovery.outcome.is_some() {
+            return;
+        }
+        // Accounts spawned this frame aren't queryable yet
+        let activity = |account: Entity| activities.get(account).copied().unwrap_or_default();
+
+        if let Some(timeout) = discovery.activity_timeout {
+            discovery
+                .accounts
+                .iter()
+                .filter(|(_, account, found)| {
+                    activity(*account) == AccountActivity::Unknown
+                        && time.elapsed().saturating_sub(*found) >= timeout
+                })
+                .for_each(|(index, account, _)| {
+                    log::warn!(
+                        "Activity of {} account {index} is still unknown after {timeout:?}, taking it as unused",
+                        A::NAME
+                    );
+                    commands.entity(*account).insert(AccountActivity::Unused);
+                });
+        }
+
+        let scan_until = discovery
+            .accounts
+            .iter()
+            .filter(|(_, account, _)| activity(*account) == AccountActivity::Used)
+            .map(|(index, _, _)| index + 1)
+            .max()
+            .unwrap_or(0)
+            .saturating_add(discovery.gap_limit);
+
+        while discovery.next_index < scan_until {
+            let index = discovery.next_index;
+            let requested = discovery.template.path(index).map_err(eyre::Report::from).and_then(
+                |path| {
+                    let id = pending.push(&mut queue, A::address_command(path.clone()))?;
+                    Ok((id, path))
+                },
+            );
+
+            match requested {
+                Ok((id, path)) => {
+                    discovery.pending.insert(id, (index, path));
+                    discovery.next_index += 1;
+                }
+                Err(err) => {
+                    discovery.outcome = Some(DiscoveryOutcome::Failed(err.to_string()));
+                    return;
+                }
+            }
+        }
+
+        let settled = discovery.pending.is_empty()
+            && discovery
+                .accounts
+                .iter()
+                .all(|(_, account, _)| activity(*account) != AccountActivity::Unknown);
+        if settled {
+            discovery.outcome = Some(DiscoveryOutcome::Completed);
+        }
+    });
+}
+
+/// End discoveries once settled, failed or cancelled, cancelling the account requests still queued
+fn end_discoveries(
+    mut discoveries: Query<(Entity, &mut AccountDiscovery)>,
+    mut cancel: EventWriter<CancelCommand>,
+    mut finished: EventWriter<DiscoveryFinished>,
+    mut commands: Commands,
+) {
+    discoveries.for_each_mut(|(device_id, mut discovery)| {
+        let Some(outcome) = discovery.outcome.clone() else {
+            return;
+        };
+
+        cancel.send_batch(discovery.pending.keys().map(|id| CancelCommand {
+            device_id,
+            command_id: *id,
+        }));
+
+        discovery
+            .accounts
+            .sort_unstable_by_key(|(index, _, _)| *index);
+        log::info!(
+            "Discovery of {} accounts of {} ended: {outcome:?}, {} found",
+            discovery.app,
+            discovery.template,
+            discovery.accounts.len()
+        );
+
+        finished.send(DiscoveryFinished {
+            device_id,
+            app: discovery.app,
+            accounts: discovery.accounts.iter().map(|(_, a, _)| *a).collect(),
+            outcome,
+        });
+        commands.entity(device_id).remove::<AccountDiscovery>();
+    });
+}
+
+fn despawn_orphan_accounts(
+    mut removed: RemovedComponents<Device>,
+    accounts: Query<(Entity, &DiscoveredAccount)>,
+    mut commands: Commands,
+) {
+    removed.iter().for_each(|device_id| {
+        accounts
+            .iter()
+            .filter(|(_, a)| a.device_id == device_id)
+            .for_each(|(account, _)| commands.entity(account).despawn());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apdu::APDUAnswer, app::LedgerApp, device::RunningApp, ethereum::Ethereum,
+        event::queue::CommandCompleted, LedgerAppPlugin,
+    };
+    use bevy::ecs::event::ManualEventReader;
+    use std::time::Instant;
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .add_event::<CommandCompleted>()
+            .add_event::<CommandCancelled>()
+            .add_event::<CancelCommand>()
+            .add_plugin(LedgerAppPlugin::<Ethereum>::default())
+            .add_plugin(DiscoveryPlugin::<Ethereum>::default());
+        let device = app.world.spawn(CommandQueue::default()).id();
+        (app, device)
+    }
+
+    fn discover(app: &mut App, device_id: Entity, gap_limit: u32, timeout: Option<Duration>) {
+        app.world.send_event(DiscoverAccounts {
+            device_id,
+            app: Ethereum::NAME,
+            template: "m/44'/60'/{account}'/0/0".parse().unwrap(),
+            gap_limit,
+            activity_timeout: timeout,
+        });
+        app.update();
+        app.update();
+    }
+
+    /// Answer the queued address requests as the device would, returning how many got answered
+    fn answer_requests(app: &mut App, device_id: Entity) -> usize {
+        let running = RunningApp {
+            name: Ethereum::NAME.to_string(),
+            version: "1.10.0".to_string(),
+        };
+        let mut queue = app.world.get_mut::<CommandQueue>(device_id).unwrap();
+        let requests: Vec<_> = std::iter::from_fn(|| queue.pop(Some(&running))).collect();
+
+        requests.iter().for_each(|request| {
+            let answer = [&[65][..], &[0x04; 65], &[40], &[b'a'; 40], &[0x90, 0x00]].concat();
+            app.world.send_event(CommandCompleted {
+                device_id,
+                command_id: request.id,
+                name: request.name,
+                result: APDUAnswer::from_answer(answer),
+                timeout: Duration::from_secs(1),
+                attempts: 1,
+            });
+        });
+        app.update();
+        app.update();
+        requests.len()
+    }
+
+    fn account(app: &mut App, index: u32) -> Entity {
+        app.world
+            .query::<(Entity, &DiscoveredAccount)>()
+            .iter(&app.world)
+            .find(|(_, a)| a.index == index)
+            .map(|(account, _)| account)
+            .unwrap()
+    }
+
+    fn set_activity(app: &mut App, index: u32, activity: AccountActivity) {
+        let account = account(app, index);
+        app.world.entity_mut(account).insert(activity);
+        app.update();
+        app.update();
+    }
+
+    /// Indexes of the accounts and outcome of the discoveries finished since the last call
+    fn finished(
+        app: &App,
+        reader: &mut ManualEventReader<DiscoveryFinished>,
+    ) -> Vec<(Vec<u32>, DiscoveryOutcome)> {
+        let events = app.world.resource::<Events<DiscoveryFinished>>();
+        reader
+            .iter(events)
+            .map(|e| {
+                let indexes = e
+                    .accounts
+                    .iter()
+                    .map(|a| app.world.get::<DiscoveredAccount>(*a).unwrap().index)
+                    .collect();
+                (indexes, e.outcome.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stops_at_gap_limit_past_last_used_account() {
+        let (mut app, device) = app();
+        let mut reader = ManualEventReader::default();
+        discover(&mut app, device, 2, None);
+        assert_eq!(answer_requests(&mut app, device), 2);
+
+        // Accounts found but not looked up yet keep the discovery running
+        assert!(finished(&app, &mut reader).is_empty());
+
+        set_activity(&mut app, 0, AccountActivity::Used);
+        assert_eq!(answer_requests(&mut app, device), 1);
+        set_activity(&mut app, 1, AccountActivity::Unused);
+        set_activity(&mut app, 2, AccountActivity::Unused);
+
+        assert_eq!(
+            finished(&app, &mut reader),
+            [(vec![0, 1, 2], DiscoveryOutcome::Completed)]
+        );
+        assert_eq!(answer_requests(&mut app, device), 0);
+    }
+
+    #[test]
+    fn cancellation_drops_pending_requests() {
+        let (mut app, device) = app();
+        let mut reader = ManualEventReader::default();
+        let mut cancelled = ManualEventReader::<CancelCommand>::default();
+        discover(&mut app, device, 3, None);
+
+        app.world.send_event(CancelDiscovery { device_id: device });
+        app.update();
+        app.update();
+
+        assert_eq!(
+            finished(&app, &mut reader),
+            [(vec![], DiscoveryOutcome::Cancelled)]
+        );
+        let events = app.world.resource::<Events<CancelCommand>>();
+        assert_eq!(cancelled.iter(events).count(), 3);
+
+        // Late answers don't spawn accounts anymore
+        assert_eq!(answer_requests(&mut app, device), 3);
+        let mut accounts = app.world.query::<&DiscoveredAccount>();
+        assert_eq!(accounts.iter(&app.world).count(), 0);
+    }
+
+    #[test]
+    fn activity_timeout_settles_unknown_accounts() {
+        let (mut app, device) = app();
+        let mut reader = ManualEventReader::default();
+        discover(&mut app, device, 1, Some(Duration::from_secs(30)));
+        assert_eq!(answer_requests(&mut app, device), 1);
+        assert!(finished(&app, &mut reader).is_empty());
+
+        let elapsed = app.world.resource::<Time>().elapsed();
+        let now = Instant::now() + elapsed + Duration::from_secs(31);
+        app.world.resource_mut::<Time>().update_with_instant(now);
+        app.update();
+        app.update();
+
+        assert_eq!(
+            finished(&app, &mut reader),
+            [(vec![0], DiscoveryOutcome::Completed)]
+        );
+        let account = account(&mut app, 0);
+        assert_eq!(
+            app.world.get::<AccountActivity>(account),
+            Some(&AccountActivity::Unused)
+        );
+    }
+}
@@ -0,0 +1,375 @@
+mod eip712;
+mod rlp;
+mod transaction;
+
+use super::{app::send_app_commands, DiscoveryPlugin, LedgerAppPlugin};
+use crate::{
+    apdu::APDUCommand,
+    app::LedgerApp,
+    constant::*,
+    discovery::DiscoverableApp,
+    error::APDUAnswerError,
+    ethereum::{
+        Erc20Descriptor, EthAppConfiguration, EthCommand, EthDescriptors, EthResponse,
+        EthSignature, Ethereum, ExternalPluginDescriptor, NftDescriptor,
+    },
+    event::app::{AppCommand, AppResponse},
+    path::DerivationPath,
+    queue::{CommandQueue, QueuedCommand},
+};
+use bevy::{log, prelude::*};
+use eip712::typed_data_apdus;
+use transaction::{sign_transaction_apdus, transaction_fields, TransactionChunks, TransactionKind};
+
+const GET_ETH_ADDRESS: &str = "GetEthAddress";
+const SIGN_ETH_TRANSACTION: &str = "SignEthTransaction";
+const SIGN_ETH_PERSONAL_MESSAGE: &str = "SignEthPersonalMessage";
+const SIGN_EIP712: &str = "SignEip712";
+const GET_ETH_APP_CONFIGURATION: &str = "GetEthAppConfiguration";
+
+/// Size of the payload of each message chunk, the first one including the derivation path
+const CHUNK_SIZE: usize = 150;
+
+pub struct EthereumPlugin;
+
+impl Plugin for EthereumPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(LedgerAppPlugin::<Ethereum>::default())
+            .add_plugin(DiscoveryPlugin::<Ethereum>::default())
+            .add_systems((
+                provide_transaction_metadata.before(send_app_commands::<Ethereum>),
+                insert_eth_app_configuration,
+            ));
+    }
+}
+
+impl LedgerApp for Ethereum {
+    const NAME: &'static str = "Ethereum";
+    const CLA: u8 = CLA_ETH;
+    type Command = EthCommand;
+    type Response = EthResponse;
+    /// Needed to rebuild `v` when signing a transaction
+    type State = Option<TransactionKind>;
+
+    fn queued(command: &EthCommand) -> eyre::Result<(QueuedCommand, Self::State)> {
+        let command = match command {
+            EthCommand::GetAddress {
+                path,
+                display,
+                chain_code,
+            } => {
+                let apdu = Self::apdu(
+                    INS_ETH_GET_ADDRESS,
+                    u8::from(*display),
+                    u8::from(*chain_code),
+                    path.serialize(),
+                );
+                let command = QueuedCommand::new(GET_ETH_ADDRESS, apdu);
+                match display {
+                    true => command.with_user_confirmation(),
+                    false => command.idempotent(),
+                }
+            }
+            EthCommand::SignTransaction { path, raw_tx } => {
+                let TransactionChunks { apdus, kind } = sign_transaction_apdus(path, raw_tx)?;
+                let command = QueuedCommand::chunked(SIGN_ETH_TRANSACTION, apdus);
+                return Ok((command.with_user_confirmation(), Some(kind)));
+            }
+            EthCommand::SignPersonalMessage { path, message } => QueuedCommand::chunked(
+                SIGN_ETH_PERSONAL_MESSAGE,
+                personal_message_apdus(path, message),
+            )
+            .with_user_confirmation(),
+            EthCommand::SignEip712Hashed {
+                path,
+                domain_hash,
+                message_hash,
+            } => {
+                let mut data = path.serialize();
+                data.extend(domain_hash);
+                data.extend(message_hash);
+
+                let apdu = Self::apdu(INS_ETH_SIGN_EIP712, 0x00, 0x00, data);
+                QueuedCommand::new(SIGN_EIP712, apdu).with_user_confirmation()
+            }
+            EthCommand::SignEip712Message { path, typed_data } => {
+                let mut apdus = typed_data_apdus(typed_data)?;
+                apdus.push(Self::apdu(
+                    INS_ETH_SIGN_EIP712,
+                    0x00,
+                    0x01,
+                    path.serialize(),
+                ));
+                QueuedCommand::chunked(SIGN_EIP712, apdus).with_user_confirmation()
+            }
+            EthCommand::GetAppConfiguration => QueuedCommand::new(
+                GET_ETH_APP_CONFIGURATION,
+                Self::apdu(INS_ETH_GET_APP_CONFIGURATION, 0x00, 0x00, Vec::new()),
+            )
+            .idempotent(),
+            EthCommand::ProvideErc20TokenInfo(token) => erc20_command(token),
+            EthCommand::SetExternalPlugin(plugin) => plugin_command(plugin),
+            EthCommand::ProvideNftInfo(nft) => nft_command(nft),
+        };
+
+        Ok((command, None))
+    }
+
+    fn decode(
+        command: &EthCommand,
+        kind: Option<TransactionKind>,
+        data: &[u8],
+    ) -> Result<EthResponse, APDUAnswerError> {
+        match command {
+            EthCommand::GetAddress { .. } => decode_address(data),
+            EthCommand::SignTransaction { .. }
+            | EthCommand::SignPersonalMessage { .. }
+            | EthCommand::SignEip712Hashed { .. }
+            | EthCommand::SignEip712Message { .. } => {
+                let RawSignature { v, r, s } = decode_signature(data)?;
+                let v = match kind {
+                    Some(kind) => kind.v(v)?,
+                    None => v as u64,
+                };
+                Ok(EthResponse::Signature(EthSignature { v, r, s }))
+            }
+            EthCommand::GetAppConfiguration => EthAppConfiguration::from_answer(data)
+                .map(EthResponse::AppConfiguration)
+                .ok_or(APDUAnswerError::Malformed("configuration is too short")),
+            EthCommand::ProvideErc20TokenInfo(_)
+            | EthCommand::SetExternalPlugin(_)
+            | EthCommand::ProvideNftInfo(_) => Ok(EthResponse::MetadataProvided),
+        }
+    }
+}
+
+impl DiscoverableApp for Ethereum {
+    fn address_command(path: DerivationPath) -> EthCommand {
+        EthCommand::GetAddress {
+            path,
+            display: false,
+            chain_code: false,
+        }
+    }
+
+    fn address(response: &EthResponse) -> Option<String> {
+        match response {
+            EthResponse::Address { address, .. } => Some(address.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Feed the metadata the app needs to clear sign a transaction ahead of it
+fn provide_transaction_metadata(
+    mut events: EventReader<AppCommand<Ethereum>>,
+    mut queues: Query<(&mut CommandQueue, Option<&EthAppConfiguration>)>,
+    descriptors: Option<Res<EthDescriptors>>,
+) {
+    events.iter().for_each(|e| {
+        let EthCommand::SignTransaction { raw_tx, .. } = &e.command else {
+            return;
+        };
+        let Ok((mut queue, configuration)) = queues.get_mut(e.device_id) else {
+            return;
+        };
+        let Ok(fields) = transaction_fields(raw_tx) else {
+            return;
+        };
+
+        let mut clear_signed = false;
+        if let (Some(descriptors), Some(to)) = (&descriptors, &fields.to) {
+            let chain_id = fields.chain_id.unwrap_or(1);
+            if let Some(token) = descriptors.erc20(chain_id, to) {
+                queue.push(erc20_command(token));
+                clear_signed = true;
+            }
+            if let Some(nft) = descriptors.nft(chain_id, to) {
+                queue.push(nft_command(nft));
+            }
+            if let Some(plugin) = fields.selector().and_then(|s| descriptors.plugin(to, &s)) {
+                queue.push(plugin_command(plugin));
+                clear_signed = true;
+            }
+        }
+
+        let blind_signing_disabled = configuration.is_some_and(|c| !c.blind_signing_enabled);
+        if !fields.data.is_empty() && !clear_signed && blind_signing_disabled {
+            log::warn!(
+                "Transaction calls a contract the app cannot decode, enable blind signing in the Ethereum app settings"
+            );
+        }
+    });
+}
+
+/// Keep the configuration of the app on the device entity
+fn insert_eth_app_configuration(
+    mut responses: EventReader<AppResponse<Ethereum>>,
+    mut commands: Commands,
+) {
+    responses.iter().for_each(|e| {
+        if let Ok(EthResponse::AppConfiguration(configuration)) = e.result {
+            if let Some(mut device) = commands.get_entity(e.device_id) {
+                device.insert(configuration);
+            }
+        }
+    });
+}
+
+/// `path | message length | message`, the first chunk holding the path and the length
+fn personal_message_apdus(path: &DerivationPath, message: &[u8]) -> Vec<APDUCommand<Vec<u8>>> {
+    let mut first = path.serialize();
+    first.extend((message.len() as u32).to_be_bytes());
+    let (head, tail) = message.split_at((CHUNK_SIZE - first.len()).min(message.len()));
+    first.extend(head);
+
+    std::iter::once(first)
+        .chain(tail.chunks(CHUNK_SIZE).map(<[u8]>::to_vec))
+        .enumerate()
+        .map(|(i, data)| {
+            let p1 = if i == 0 { 0x00 } else { 0x80 };
+            Ethereum::apdu(INS_ETH_SIGN_PERSONAL_MESSAGE, p1, 0x00, data)
+        })
+        .collect()
+}
+
+fn erc20_command(token: &Erc20Descriptor) -> QueuedCommand {
+    let apdu = Ethereum::apdu(
+        INS_ETH_PROVIDE_ERC20_TOKEN_INFO,
+        0x00,
+        0x00,
+        token.payload(),
+    );
+    QueuedCommand::new("ProvideErc20TokenInfo", apdu).for_app(Ethereum::NAME)
+}
+
+fn plugin_command(plugin: &ExternalPluginDescriptor) -> QueuedCommand {
+    let apdu = Ethereum::apdu(INS_ETH_SET_EXTERNAL_PLUGIN, 0x00, 0x00, plugin.payload());
+    QueuedCommand::new("SetEthExternalPlugin", apdu).for_app(Ethereum::NAME)
+}
+
+fn nft_command(nft: &NftDescriptor) -> QueuedCommand {
+    let apdu = Ethereum::apdu(INS_ETH_PROVIDE_NFT_INFO, 0x00, 0x00, nft.payload());
+    QueuedCommand::new("ProvideNftInfo", apdu).for_app(Ethereum::NAME)
+}
+
+/// Signature as answered by the device, `v` being truncated to a byte
+struct RawSignature {
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+}
+
+/// Decode `v | r | s`
+fn decode_signature(data: &[u8]) -> Result<RawSignature, APDUAnswerError> {
+    if data.len() != 65 {
+        return Err(APDUAnswerError::Malformed("signature is not 65 bytes long"));
+    }
+
+    Ok(RawSignature {
+        v: data[0],
+        r: *arrayref::array_ref!(data, 1, 32),
+        s: *arrayref::array_ref!(data, 33, 32),
+    })
+}
+
+/// Decode `public key length | public key | address length | address | chain code?`
+fn decode_address(data: &[u8]) -> Result<EthResponse, APDUAnswerError> {
+    let (public_key, rest) = split_prefixed(data)?;
+    let (address, rest) = split_prefixed(rest)?;
+    let address = std::str::from_utf8(address)
+        .map_err(|_| APDUAnswerError::Malformed("address is not ASCII"))?;
+
+    let chain_code = match rest.len() {
+        0 => None,
+        32 => Some(*arrayref::array_ref!(rest, 0, 32)),
+        _ => return Err(APDUAnswerError::Malformed("unexpected trailing bytes")),
+    };
+
+    Ok(EthResponse::Address {
+        public_key: public_key.to_vec(),
+        address: format!("0x{address}"),
+        chain_code,
+    })
+}
+
+/// Split a length prefixed field off the front of `data`
+fn split_prefixed(data: &[u8]) -> Result<(&[u8], &[u8]), APDUAnswerError> {
+    let (len, rest) = data
+        .split_first()
+        .ok_or(APDUAnswerError::Malformed("missing length"))?;
+    if rest.len() < *len as usize {
+        return Err(APDUAnswerError::Malformed("field shorter than its length"));
+    }
+
+    Ok(rest.split_at(*len as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_personal_message() {
+        let path: DerivationPath = "m/44'/60'/0'/0/0".parse().unwrap();
+        let message: Vec<u8> = (0..=255).collect();
+        let apdus = personal_message_apdus(&path, &message);
+
+        // 21 bytes of path and 4 of length leave 125 bytes of message in the first chunk
+        assert_eq!(apdus.len(), 2);
+        assert_eq!((apdus[0].p1, apdus[1].p1), (0x00, 0x80));
+        assert_eq!(apdus[0].data.len(), CHUNK_SIZE);
+        assert_eq!(apdus[0].data[..21], path.serialize());
+        assert_eq!(apdus[0].data[21..25], [0, 0, 1, 0]);
+        assert_eq!(apdus[0].data[25..], message[..125]);
+        assert_eq!(apdus[1].data, message[125..]);
+        assert!(apdus
+            .iter()
+            .all(|apdu| (apdu.cla, apdu.ins) == (CLA_ETH, INS_ETH_SIGN_PERSONAL_MESSAGE)));
+    }
+
+    #[test]
+    fn short_personal_message_fits_one_chunk() {
+        let apdus = personal_message_apdus(&"m/44'".parse().unwrap(), b"hello");
+
+        assert_eq!(apdus.len(), 1);
+        assert_eq!(apdus[0].p1, 0x00);
+        assert_eq!(
+            apdus[0].data,
+            [&[1, 0x80, 0, 0, 0x2c, 0, 0, 0, 5][..], b"hello"].concat()
+        );
+    }
+
+    #[test]
+    fn decodes_signature() {
+        let data = [&[0x25][..], &[0x11; 32], &[0x22; 32]].concat();
+
+        let signature = decode_signature(&data).unwrap();
+        assert_eq!(signature.v, 0x25);
+        assert_eq!(signature.r, [0x11; 32]);
+        assert_eq!(signature.s, [0x22; 32]);
+    }
+
+    #[test]
+    fn rebuilds_transaction_v() {
+        let command = EthCommand::SignTransaction {
+            path: DerivationPath::default(),
+            raw_tx: Vec::new(),
+        };
+        let data = [&[0x26][..], &[0x11; 32], &[0x22; 32]].concat();
+
+        // Chain id 1 gives `v` 37 or 38 following EIP-155
+        let kind = TransactionKind::Legacy { chain_id: Some(1) };
+        let response = Ethereum::decode(&command, Some(kind), &data).unwrap();
+        let EthResponse::Signature(signature) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(signature.v, 38);
+    }
+
+    #[test]
+    fn malformed_signature() {
+        assert!(decode_signature(&[0x25, 0x11]).is_err());
+        assert!(decode_signature(&[]).is_err());
+    }
+}
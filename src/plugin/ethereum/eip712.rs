@@ -0,0 +1,560 @@
+//! Streaming EIP-712 typed data to the device for full (non hashed) signing
+
+use crate::{
+    apdu::APDUCommand,
+    constant::{CLA_ETH, INS_ETH_EIP712_STRUCT_DEFINITION, INS_ETH_EIP712_STRUCT_IMPLEMENTATION},
+};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Maximum payload of an APDU
+const MAX_APDU_DATA: usize = 255;
+
+const P2_STRUCT_NAME: u8 = 0x00;
+const P2_ARRAY: u8 = 0x0f;
+const P2_FIELD: u8 = 0xff;
+
+const P1_COMPLETE: u8 = 0x00;
+const P1_PARTIAL: u8 = 0x01;
+
+#[derive(Error, Debug)]
+pub enum Eip712Error {
+    #[error("EIP-712: missing `{0}`")]
+    Missing(String),
+    #[error("EIP-712: unknown type `{0}`")]
+    UnknownType(String),
+    #[error("EIP-712: invalid value for `{0}`")]
+    InvalidValue(String),
+}
+
+/// Type of a struct field, as sent to the device
+#[derive(Debug, PartialEq, Eq)]
+enum FieldType {
+    Custom(String),
+    Int(u8),
+    Uint(u8),
+    Address,
+    Bool,
+    String,
+    FixedBytes(u8),
+    DynamicBytes,
+}
+
+impl FieldType {
+    fn parse(name: &str, types: &Map<String, Value>) -> Result<Self, Eip712Error> {
+        let sized = |prefix: &str, default: u16| -> Option<u16> {
+            let size = name.strip_prefix(prefix)?;
+            match size {
+                "" => Some(default),
+                size => size.parse().ok(),
+            }
+        };
+
+        let field_type = match name {
+            "address" => Self::Address,
+            "bool" => Self::Bool,
+            "string" => Self::String,
+            "bytes" => Self::DynamicBytes,
+            _ if types.contains_key(name) => Self::Custom(name.to_string()),
+            _ => {
+                if let Some(bits) = sized("uint", 0) {
+                    Self::Uint(bits_to_bytes(bits, name)?)
+                } else if let Some(bits) = sized("int", 0) {
+                    Self::Int(bits_to_bytes(bits, name)?)
+                } else if let Some(size @ 1..=32) = sized("bytes", 0) {
+                    Self::FixedBytes(size as u8)
+                } else {
+                    return Err(Eip712Error::UnknownType(name.to_string()));
+                }
+            }
+        };
+
+        Ok(field_type)
+    }
+
+    /// Type descriptor low bits and optional size
+    fn descriptor(&self) -> (u8, Option<u8>) {
+        match self {
+            Self::Custom(_) => (0, None),
+            Self::Int(size) => (1, Some(*size)),
+            Self::Uint(size) => (2, Some(*size)),
+            Self::Address => (3, None),
+            Self::Bool => (4, None),
+            Self::String => (5, None),
+            Self::FixedBytes(size) => (6, Some(*size)),
+            Self::DynamicBytes => (7, None),
+        }
+    }
+}
+
+fn bits_to_bytes(bits: u16, name: &str) -> Result<u8, Eip712Error> {
+    match bits {
+        // `uint` and `int` are aliases of their 256 bits version
+        0 => Ok(32),
+        8..=256 if bits % 8 == 0 => Ok((bits / 8) as u8),
+        _ => Err(Eip712Error::UnknownType(name.to_string())),
+    }
+}
+
+/// Array levels of a type, e.g. `Person[2][]`, from the innermost to the outermost
+fn split_array_levels(name: &str) -> Result<(&str, Vec<Option<u8>>), Eip712Error> {
+    let base_end = name.find('[').unwrap_or(name.len());
+    let levels = name[base_end..]
+        .split_terminator(']')
+        .map(|level| match level.strip_prefix('[') {
+            Some("") => Ok(None),
+            Some(size) => size
+                .parse()
+                .map(Some)
+                .map_err(|_| Eip712Error::UnknownType(name.to_string())),
+            None => Err(Eip712Error::UnknownType(name.to_string())),
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((&name[..base_end], levels))
+}
+
+/// Fields of a struct as `(name, type)`
+fn struct_fields<'a>(
+    types: &'a Map<String, Value>,
+    name: &str,
+) -> Result<Vec<(&'a str, &'a str)>, Eip712Error> {
+    types
+        .get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(|| Eip712Error::Missing(format!("types.{name}")))?
+        .iter()
+        .map(|field| {
+            let field_name = field.get("name").and_then(Value::as_str);
+            let field_type = field.get("type").and_then(Value::as_str);
+            field_name
+                .zip(field_type)
+                .ok_or_else(|| Eip712Error::InvalidValue(format!("types.{name}")))
+        })
+        .collect()
+}
+
+/// APDUs sending the struct definitions, then the domain and message implementations of `typed_data`
+pub fn typed_data_apdus(typed_data: &Value) -> Result<Vec<APDUCommand<Vec<u8>>>, Eip712Error> {
+    let types = typed_data
+        .get("types")
+        .and_then(Value::as_object)
+        .ok_or_else(|| Eip712Error::Missing("types".into()))?;
+    let primary_type = typed_data
+        .get("primaryType")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Eip712Error::Missing("primaryType".into()))?;
+    let domain = typed_data
+        .get("domain")
+        .ok_or_else(|| Eip712Error::Missing("domain".into()))?;
+    let message = typed_data
+        .get("message")
+        .ok_or_else(|| Eip712Error::Missing("message".into()))?;
+
+    let mut apdus = Vec::new();
+
+    for name in types.keys() {
+        apdus.push(apdu(
+            INS_ETH_EIP712_STRUCT_DEFINITION,
+            P1_COMPLETE,
+            P2_STRUCT_NAME,
+            name.as_bytes().to_vec(),
+        ));
+
+        for (field_name, field_type) in struct_fields(types, name)? {
+            apdus.push(apdu(
+                INS_ETH_EIP712_STRUCT_DEFINITION,
+                P1_COMPLETE,
+                P2_FIELD,
+                field_definition(field_name, field_type, types)?,
+            ));
+        }
+    }
+
+    for (name, value) in [("EIP712Domain", domain), (primary_type, message)] {
+        apdus.push(apdu(
+            INS_ETH_EIP712_STRUCT_IMPLEMENTATION,
+            P1_COMPLETE,
+            P2_STRUCT_NAME,
+            name.as_bytes().to_vec(),
+        ));
+        struct_implementation(name, value, types, &mut apdus)?;
+    }
+
+    Ok(apdus)
+}
+
+/// `type descriptor | type name? | type size? | array levels? | key name`
+fn field_definition(
+    name: &str,
+    type_name: &str,
+    types: &Map<String, Value>,
+) -> Result<Vec<u8>, Eip712Error> {
+    let (base, levels) = split_array_levels(type_name)?;
+    let field_type = FieldType::parse(base, types)?;
+    let (mut descriptor, size) = field_type.descriptor();
+    if !levels.is_empty() {
+        descriptor |= 0x80;
+    }
+    if size.is_some() {
+        descriptor |= 0x40;
+    }
+
+    let mut data = vec![descriptor];
+    if let FieldType::Custom(custom) = &field_type {
+        data.push(custom.len() as u8);
+        data.extend(custom.as_bytes());
+    }
+    data.extend(size);
+    if !levels.is_empty() {
+        data.push(levels.len() as u8);
+        levels.iter().for_each(|level| match level {
+            None => data.push(0),
+            Some(size) => data.extend([1, *size]),
+        });
+    }
+    data.push(name.len() as u8);
+    data.extend(name.as_bytes());
+
+    Ok(data)
+}
+
+fn struct_implementation(
+    name: &str,
+    value: &Value,
+    types: &Map<String, Value>,
+    apdus: &mut Vec<APDUCommand<Vec<u8>>>,
+) -> Result<(), Eip712Error> {
+    for (field_name, field_type) in struct_fields(types, name)? {
+        let field_value = value
+            .get(field_name)
+            .ok_or_else(|| Eip712Error::Missing(format!("{name}.{field_name}")))?;
+        let (base, levels) = split_array_levels(field_type)?;
+        field_implementation(
+            field_name,
+            &FieldType::parse(base, types)?,
+            levels.len(),
+            field_value,
+            types,
+            apdus,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn field_implementation(
+    name: &str,
+    field_type: &FieldType,
+    array_depth: usize,
+    value: &Value,
+    types: &Map<String, Value>,
+    apdus: &mut Vec<APDUCommand<Vec<u8>>>,
+) -> Result<(), Eip712Error> {
+    if array_depth > 0 {
+        let items = value
+            .as_array()
+            .ok_or_else(|| Eip712Error::InvalidValue(name.to_string()))?;
+        apdus.push(apdu(
+            INS_ETH_EIP712_STRUCT_IMPLEMENTATION,
+            P1_COMPLETE,
+            P2_ARRAY,
+            vec![items.len() as u8],
+        ));
+        for item in items {
+            field_implementation(name, field_type, array_depth - 1, item, types, apdus)?;
+        }
+        return Ok(());
+    }
+
+    if let FieldType::Custom(custom) = field_type {
+        return struct_implementation(custom, value, types, apdus);
+    }
+
+    let encoded = encode_value(field_type, value)
+        .ok_or_else(|| Eip712Error::InvalidValue(name.to_string()))?;
+    let mut data = (encoded.len() as u16).to_be_bytes().to_vec();
+    data.extend(encoded);
+
+    let chunks: Vec<_> = data.chunks(MAX_APDU_DATA).collect();
+    let last = chunks.len() - 1;
+    apdus.extend(chunks.into_iter().enumerate().map(|(i, chunk)| {
+        let p1 = if i == last { P1_COMPLETE } else { P1_PARTIAL };
+        apdu(
+            INS_ETH_EIP712_STRUCT_IMPLEMENTATION,
+            p1,
+            P2_FIELD,
+            chunk.to_vec(),
+        )
+    }));
+
+    Ok(())
+}
+
+fn encode_value(field_type: &FieldType, value: &Value) -> Option<Vec<u8>> {
+    match field_type {
+        FieldType::Uint(_) => {
+            parse_integer(value).and_then(|(negative, magnitude)| (!negative).then_some(magnitude))
+        }
+        FieldType::Int(size) => {
+            let (negative, magnitude) = parse_integer(value)?;
+            let size = *size as usize;
+            if magnitude.len() > size {
+                return None;
+            }
+            let mut bytes = vec![0u8; size - magnitude.len()];
+            bytes.extend(magnitude);
+            if negative {
+                twos_complement(&mut bytes);
+            }
+            Some(bytes)
+        }
+        FieldType::Address | FieldType::FixedBytes(_) | FieldType::DynamicBytes => {
+            let hex = value.as_str()?;
+            hex::decode(hex.strip_prefix("0x").unwrap_or(hex)).ok()
+        }
+        FieldType::Bool => value.as_bool().map(|b| vec![u8::from(b)]),
+        FieldType::String => value.as_str().map(|s| s.as_bytes().to_vec()),
+        FieldType::Custom(_) => None,
+    }
+}
+
+/// Parse a JSON number, decimal or `0x` prefixed hex string into its sign and minimal big-endian magnitude
+fn parse_integer(value: &Value) -> Option<(bool, Vec<u8>)> {
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return None,
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+
+    let mut magnitude = match digits.strip_prefix("0x") {
+        Some(hex) if hex.len() % 2 == 1 => hex::decode(format!("0{hex}")).ok()?,
+        Some(hex) => hex::decode(hex).ok()?,
+        None => {
+            if digits.is_empty() {
+                return None;
+            }
+            digits.chars().try_fold(Vec::new(), |mut bytes, c| {
+                let mut carry = c.to_digit(10)?;
+                for byte in bytes.iter_mut().rev() {
+                    let v = *byte as u32 * 10 + carry;
+                    *byte = v as u8;
+                    carry = v >> 8;
+                }
+                if carry > 0 {
+                    bytes.insert(0, carry as u8);
+                }
+                Some(bytes)
+            })?
+        }
+    };
+
+    let leading_zeros = magnitude.iter().take_while(|b| **b == 0).count();
+    magnitude.drain(..leading_zeros);
+    if magnitude.is_empty() {
+        magnitude.push(0);
+    }
+
+    Some((negative, magnitude))
+}
+
+fn twos_complement(bytes: &mut [u8]) {
+    let mut carry = true;
+    for byte in bytes.iter_mut().rev() {
+        let (v, overflow) = (!*byte).overflowing_add(u8::from(carry));
+        *byte = v;
+        carry = overflow;
+    }
+}
+
+fn apdu(ins: u8, p1: u8, p2: u8, data: Vec<u8>) -> APDUCommand<Vec<u8>> {
+    APDUCommand {
+        cla: CLA_ETH,
+        ins,
+        p1,
+        p2,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn integer(value: Value) -> Option<(bool, Vec<u8>)> {
+        parse_integer(&value)
+    }
+
+    fn types(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn parses_integers() {
+        assert_eq!(integer(json!(0)), Some((false, vec![0])));
+        assert_eq!(integer(json!(1000)), Some((false, vec![0x03, 0xe8])));
+        assert_eq!(integer(json!(-1)), Some((true, vec![1])));
+        assert_eq!(integer(json!("0x0")), Some((false, vec![0])));
+        assert_eq!(integer(json!("0x00ff")), Some((false, vec![0xff])));
+        assert_eq!(integer(json!("0x123")), Some((false, vec![0x01, 0x23])));
+        assert_eq!(integer(json!("-0x80")), Some((true, vec![0x80])));
+
+        // 2^64, beyond what JSON numbers hold
+        assert_eq!(
+            integer(json!("18446744073709551616")),
+            Some((false, vec![1, 0, 0, 0, 0, 0, 0, 0, 0]))
+        );
+
+        assert_eq!(integer(json!("")), None);
+        assert_eq!(integer(json!("12a")), None);
+        assert_eq!(integer(json!("0xzz")), None);
+        assert_eq!(integer(json!(true)), None);
+    }
+
+    #[test]
+    fn encodes_signed_integers_in_twos_complement() {
+        let encode = |bits: u8, value: Value| encode_value(&FieldType::Int(bits), &value);
+
+        assert_eq!(encode(1, json!(-1)), Some(vec![0xff]));
+        assert_eq!(encode(1, json!(-128)), Some(vec![0x80]));
+        assert_eq!(encode(1, json!(127)), Some(vec![0x7f]));
+        assert_eq!(encode(2, json!(-256)), Some(vec![0xff, 0x00]));
+        assert_eq!(encode(2, json!("-0x1234")), Some(vec![0xed, 0xcc]));
+        assert_eq!(encode(32, json!(-1)), Some(vec![0xff; 32]));
+        assert_eq!(encode(1, json!(256)), None);
+
+        let uint = |value: Value| encode_value(&FieldType::Uint(32), &value);
+        assert_eq!(uint(json!(1000)), Some(vec![0x03, 0xe8]));
+        assert_eq!(uint(json!(-1)), None);
+    }
+
+    #[test]
+    fn encodes_field_definitions() {
+        let types = types(json!({ "Person": [{ "name": "name", "type": "string" }] }));
+        let definition = |name, type_name| field_definition(name, type_name, &types).unwrap();
+
+        assert_eq!(definition("ok", "bool"), [0x04, 2, b'o', b'k']);
+        assert_eq!(definition("v", "uint"), [0x42, 32, 1, b'v']);
+        assert_eq!(definition("v", "int8"), [0x41, 1, 1, b'v']);
+        assert_eq!(definition("v", "bytes4"), [0x46, 4, 1, b'v']);
+        assert_eq!(
+            definition("to", "Person"),
+            [&[0x00, 6][..], b"Person", &[2], b"to"].concat()
+        );
+        assert_eq!(
+            definition("to", "Person[]"),
+            [&[0x80, 6][..], b"Person", &[1, 0, 2], b"to"].concat()
+        );
+        // Array levels go from the innermost to the outermost
+        assert_eq!(
+            definition("h", "bytes32[2][]"),
+            [0xc6, 32, 2, 1, 2, 0, 1, b'h']
+        );
+
+        assert!(field_definition("v", "uint7", &types).is_err());
+        assert!(field_definition("v", "bytes33", &types).is_err());
+        assert!(field_definition("v", "Unknown", &types).is_err());
+        assert!(field_definition("v", "bool[x]", &types).is_err());
+    }
+
+    #[test]
+    fn streams_definitions_then_implementations() {
+        let typed_data = json!({
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Mail": [{ "name": "to", "type": "address[]" }],
+            },
+            "primaryType": "Mail",
+            "domain": { "name": "Ether Mail" },
+            "message": { "to": ["0x0101010101010101010101010101010101010101"] },
+        });
+        let apdus = typed_data_apdus(&typed_data).unwrap();
+        let sent: Vec<_> = apdus
+            .iter()
+            .map(|apdu| (apdu.ins, apdu.p1, apdu.p2, apdu.data.clone()))
+            .collect();
+
+        let definition = INS_ETH_EIP712_STRUCT_DEFINITION;
+        let implementation = INS_ETH_EIP712_STRUCT_IMPLEMENTATION;
+        assert_eq!(
+            sent,
+            [
+                (definition, 0x00, 0x00, b"EIP712Domain".to_vec()),
+                (definition, 0x00, 0xff, [&[0x05, 4][..], b"name"].concat()),
+                (definition, 0x00, 0x00, b"Mail".to_vec()),
+                (definition, 0x00, 0xff, vec![0x83, 1, 0, 2, b't', b'o']),
+                (implementation, 0x00, 0x00, b"EIP712Domain".to_vec()),
+                (
+                    implementation,
+                    0x00,
+                    0xff,
+                    [&[0, 10][..], b"Ether Mail"].concat()
+                ),
+                (implementation, 0x00, 0x00, b"Mail".to_vec()),
+                (implementation, 0x00, 0x0f, vec![1]),
+                (
+                    implementation,
+                    0x00,
+                    0xff,
+                    [vec![0, 20], vec![1; 20]].concat()
+                ),
+            ]
+        );
+        assert!(apdus.iter().all(|apdu| apdu.cla == CLA_ETH));
+    }
+
+    #[test]
+    fn chunks_long_values() {
+        let contents = "a".repeat(600);
+        let typed_data = json!({
+            "types": {
+                "EIP712Domain": [],
+                "Note": [{ "name": "contents", "type": "string" }],
+            },
+            "primaryType": "Note",
+            "domain": {},
+            "message": { "contents": contents },
+        });
+        let apdus = typed_data_apdus(&typed_data).unwrap();
+        let chunks = &apdus[apdus.len() - 3..];
+
+        assert_eq!(
+            chunks.iter().map(|apdu| apdu.p1).collect::<Vec<_>>(),
+            [P1_PARTIAL, P1_PARTIAL, P1_COMPLETE]
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|apdu| apdu.data.len())
+                .collect::<Vec<_>>(),
+            [255, 255, 92]
+        );
+        let payload: Vec<u8> = chunks.iter().flat_map(|apdu| apdu.data.clone()).collect();
+        assert_eq!(payload[..2], [0x02, 0x58]);
+        assert_eq!(payload[2..], *contents.as_bytes());
+    }
+
+    #[test]
+    fn rejects_invalid_typed_data() {
+        assert!(matches!(
+            typed_data_apdus(&json!({ "types": {} })),
+            Err(Eip712Error::Missing(_))
+        ));
+
+        let typed_data = json!({
+            "types": { "EIP712Domain": [], "Mail": [{ "name": "ok", "type": "bool" }] },
+            "primaryType": "Mail",
+            "domain": {},
+            "message": { "ok": "yes" },
+        });
+        assert!(matches!(
+            typed_data_apdus(&typed_data),
+            Err(Eip712Error::InvalidValue(field)) if field == "ok"
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+//! Just enough RLP decoding to find item boundaries in a serialized transaction
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RlpError {
+    #[error("RLP: unexpected end of data")]
+    UnexpectedEnd,
+    #[error("RLP: expected a list")]
+    NotAList,
+    #[error("RLP: trailing bytes after the list")]
+    TrailingBytes,
+}
+
+/// Position of an item inside the decoded data
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    /// Offset of the item's header
+    pub offset: usize,
+    /// Offset of the item's payload
+    pub payload_offset: usize,
+    pub payload_len: usize,
+}
+
+impl Item {
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.payload_offset..self.payload_offset + self.payload_len]
+    }
+}
+
+/// Decode the header of the item starting at `offset`, returning the item and whether it is a list
+fn decode_item(data: &[u8], offset: usize) -> Result<(Item, bool), RlpError> {
+    let prefix = *data.get(offset).ok_or(RlpError::UnexpectedEnd)?;
+
+    let (header_len, payload_len, is_list) = match prefix {
+        0x00..=0x7f => (0, 1, false),
+        0x80..=0xb7 => (1, (prefix - 0x80) as usize, false),
+        0xb8..=0xbf => {
+            let len_of_len = (prefix - 0xb7) as usize;
+            (
+                1 + len_of_len,
+                read_len(data, offset + 1, len_of_len)?,
+                false,
+            )
+        }
+        0xc0..=0xf7 => (1, (prefix - 0xc0) as usize, true),
+        0xf8..=0xff => {
+            let len_of_len = (prefix - 0xf7) as usize;
+            (
+                1 + len_of_len,
+                read_len(data, offset + 1, len_of_len)?,
+                true,
+            )
+        }
+    };
+
+    let item = Item {
+        offset,
+        payload_offset: offset + header_len,
+        payload_len,
+    };
+    // The length prefix comes from the caller, it may not even fit the address space
+    match item.payload_offset.checked_add(payload_len) {
+        Some(end) if end <= data.len() => {}
+        _ => return Err(RlpError::UnexpectedEnd),
+    }
+
+    Ok((item, is_list))
+}
+
+fn read_len(data: &[u8], offset: usize, len_of_len: usize) -> Result<usize, RlpError> {
+    let bytes = data
+        .get(offset..offset + len_of_len)
+        .ok_or(RlpError::UnexpectedEnd)?;
+
+    Ok(bytes.iter().fold(0, |len, b| (len << 8) | *b as usize))
+}
+
+/// Items of the list spanning the whole `data`, offsets being relative to `data`
+pub fn list_items(data: &[u8]) -> Result<Vec<Item>, RlpError> {
+    let (list, is_list) = decode_item(data, 0)?;
+    if !is_list {
+        return Err(RlpError::NotAList);
+    }
+    let end = list.payload_offset + list.payload_len;
+    if end != data.len() {
+        return Err(RlpError::TrailingBytes);
+    }
+
+    let mut items = Vec::new();
+    let mut offset = list.payload_offset;
+    while offset < end {
+        let (item, _) = decode_item(&data[..end], offset)?;
+        offset = item.payload_offset + item.payload_len;
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_list_items() {
+        // [0x01, "dog", []]
+        let data = [0xc6, 0x01, 0x83, b'd', b'o', b'g', 0xc0];
+        let items = list_items(&data).unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].payload(&data), [0x01]);
+        assert_eq!(items[1].payload(&data), b"dog");
+        assert_eq!(items[2].offset, 6);
+        assert!(items[2].payload(&data).is_empty());
+    }
+
+    #[test]
+    fn long_string_item() {
+        let mut data = vec![0xf8, 58, 0xb8, 56];
+        data.extend([0xaa; 56]);
+        let items = list_items(&data).unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].payload_offset, 4);
+        assert_eq!(items[0].payload_len, 56);
+    }
+
+    #[test]
+    fn malformed_lists() {
+        assert_eq!(list_items(&[0x83, 1, 2, 3]), Err(RlpError::NotAList));
+        assert_eq!(
+            list_items(&[0xc1, 0x01, 0x02]),
+            Err(RlpError::TrailingBytes)
+        );
+        assert_eq!(list_items(&[0xc3, 0x01]), Err(RlpError::UnexpectedEnd));
+        assert_eq!(list_items(&[]), Err(RlpError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn hostile_length_prefix() {
+        let data = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(list_items(&data), Err(RlpError::UnexpectedEnd));
+
+        let data = [0xc9, 0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(list_items(&data), Err(RlpError::UnexpectedEnd));
+    }
+}
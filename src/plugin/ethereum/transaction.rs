@@ -0,0 +1,272 @@
+//! Splitting raw transactions into `SIGN TRANSACTION` chunks
+
+use super::rlp::{self, RlpError};
+use crate::{
+    apdu::APDUCommand,
+    constant::{CLA_ETH, INS_ETH_SIGN_TRANSACTION},
+    error::APDUAnswerError,
+    path::DerivationPath,
+};
+
+/// Size of the payload of each chunk, the first one including the derivation path
+const CHUNK_SIZE: usize = 150;
+
+/// What is needed to rebuild `v` from the device's answer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransactionKind {
+    /// Legacy transaction, replay protected by EIP-155 when the chain id is set
+    Legacy { chain_id: Option<u64> },
+    /// EIP-2718 typed transaction, e.g. EIP-2930 or EIP-1559
+    Typed(u8),
+}
+
+impl TransactionKind {
+    /// Rebuild `v` as the device only answers its lowest byte, failing when it doesn't fit the chain id
+    pub fn v(&self, answered: u8) -> Result<u64, APDUAnswerError> {
+        match *self {
+            Self::Legacy {
+                chain_id: Some(chain_id),
+            } => {
+                let truncated = (chain_id & 0xffff_ffff) * 2 + 35;
+                let parity = answered.abs_diff((truncated % 256) as u8) as u64;
+                chain_id
+                    .checked_mul(2)
+                    .and_then(|v| v.checked_add(35 + parity))
+                    .ok_or(APDUAnswerError::Malformed("v overflows for this chain id"))
+            }
+            Self::Legacy { chain_id: None } | Self::Typed(_) => Ok(answered as u64),
+        }
+    }
+}
+
+/// Fields of a transaction deciding which metadata the app needs to clear sign it
+pub struct TransactionFields {
+    pub chain_id: Option<u64>,
+    /// Recipient, `None` for contract creations
+    pub to: Option<[u8; 20]>,
+    /// Contract call data
+    pub data: Vec<u8>,
+}
+
+impl TransactionFields {
+    /// Selector of the called contract method
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        self.data.get(..4).map(|s| *arrayref::array_ref!(s, 0, 4))
+    }
+}
+
+pub fn transaction_fields(raw_tx: &[u8]) -> Result<TransactionFields, RlpError> {
+    let first = *raw_tx.first().ok_or(RlpError::UnexpectedEnd)?;
+    // Index of the chain id, recipient and data fields
+    let (body, chain_id, to, data) = match first {
+        // EIP-2930: chainId, nonce, gasPrice, gasLimit, to, value, data, accessList
+        0x01 => (&raw_tx[1..], Some(0), 4, 6),
+        // EIP-1559: chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gasLimit, to, value, data, accessList
+        0x02 => (&raw_tx[1..], Some(0), 5, 7),
+        // Unknown transaction type
+        0x00..=0x7f => {
+            return Ok(TransactionFields {
+                chain_id: None,
+                to: None,
+                data: Vec::new(),
+            })
+        }
+        // Legacy: nonce, gasPrice, gasLimit, to, value, data, chainId?, 0?, 0?
+        _ => (raw_tx, Some(6), 3, 5),
+    };
+
+    let items = rlp::list_items(body)?;
+    let field = |i: usize| items.get(i).map(|item| item.payload(body));
+
+    Ok(TransactionFields {
+        chain_id: chain_id.and_then(field).map(be_u64),
+        to: field(to).and_then(|to| to.try_into().ok()),
+        data: field(data).unwrap_or_default().to_vec(),
+    })
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |v, b| (v << 8) | *b as u64)
+}
+
+/// Chunks of a transaction to sign
+pub struct TransactionChunks {
+    pub apdus: Vec<APDUCommand<Vec<u8>>>,
+    pub kind: TransactionKind,
+}
+
+/// Split a raw RLP encoded transaction into APDUs, making sure no chunk ends right before the EIP-155 `v, r, s` marker of legacy transactions
+pub fn sign_transaction_apdus(
+    path: &DerivationPath,
+    raw_tx: &[u8],
+) -> Result<TransactionChunks, RlpError> {
+    let first = *raw_tx.first().ok_or(RlpError::UnexpectedEnd)?;
+    let (kind, vrs_offset) = if first <= 0x7f {
+        rlp::list_items(&raw_tx[1..])?;
+        (TransactionKind::Typed(first), None)
+    } else {
+        let items = rlp::list_items(raw_tx)?;
+        match items.len() {
+            len if len > 6 => {
+                let chain_id = be_u64(items[6].payload(raw_tx));
+                (
+                    TransactionKind::Legacy {
+                        chain_id: Some(chain_id),
+                    },
+                    Some(items[len - 3].offset),
+                )
+            }
+            _ => (TransactionKind::Legacy { chain_id: None }, None),
+        }
+    };
+
+    let path = path.serialize();
+    let mut apdus = Vec::new();
+    let mut offset = 0;
+
+    while offset != raw_tx.len() {
+        let is_first = offset == 0;
+        let max_size = if is_first {
+            CHUNK_SIZE - path.len()
+        } else {
+            CHUNK_SIZE
+        };
+        let mut size = max_size.min(raw_tx.len() - offset);
+        if vrs_offset.is_some_and(|vrs| offset + size >= vrs) {
+            size = raw_tx.len() - offset;
+        }
+
+        let mut data = if is_first { path.clone() } else { Vec::new() };
+        data.extend_from_slice(&raw_tx[offset..offset + size]);
+
+        apdus.push(APDUCommand {
+            cla: CLA_ETH,
+            ins: INS_ETH_SIGN_TRANSACTION,
+            p1: if is_first { 0x00 } else { 0x80 },
+            p2: 0x00,
+            data,
+        });
+        offset += size;
+    }
+
+    Ok(TransactionChunks { apdus, kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+        match bytes {
+            [b] if *b <= 0x7f => vec![*b],
+            _ if bytes.len() <= 55 => [&[0x80 + bytes.len() as u8], bytes].concat(),
+            _ => [&[0xb8, bytes.len() as u8], bytes].concat(),
+        }
+    }
+
+    fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let payload = items.concat();
+        match payload.len() {
+            len if len <= 55 => [vec![0xc0 + len as u8], payload].concat(),
+            len => [vec![0xf8, len as u8], payload].concat(),
+        }
+    }
+
+    /// Legacy EIP-155 transaction on chain 1 carrying `data`
+    fn legacy_tx(data: &[u8]) -> Vec<u8> {
+        rlp_list(&[
+            rlp_bytes(&[]),
+            rlp_bytes(&[0x01]),
+            rlp_bytes(&[0x52, 0x08]),
+            rlp_bytes(&[0x11; 20]),
+            rlp_bytes(&[]),
+            rlp_bytes(data),
+            rlp_bytes(&[0x01]),
+            rlp_bytes(&[]),
+            rlp_bytes(&[]),
+        ])
+    }
+
+    fn path() -> DerivationPath {
+        "m/44'/60'/0'/0/0".parse().unwrap()
+    }
+
+    /// Transaction bytes carried by the chunks, without the derivation path
+    fn reassemble(chunks: &TransactionChunks) -> Vec<u8> {
+        let path_len = path().serialize().len();
+        chunks
+            .apdus
+            .iter()
+            .enumerate()
+            .flat_map(|(i, apdu)| apdu.data[if i == 0 { path_len } else { 0 }..].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn chunks_typed_transaction() {
+        let mut tx = vec![0x02];
+        tx.extend(rlp_list(&[rlp_bytes(&[0x01]), rlp_bytes(&[0xaa; 200])]));
+        let chunks = sign_transaction_apdus(&path(), &tx).unwrap();
+
+        assert_eq!(chunks.kind, TransactionKind::Typed(0x02));
+        assert_eq!(chunks.apdus.len(), 2);
+        assert_eq!(chunks.apdus[0].data.len(), CHUNK_SIZE);
+        assert_eq!(chunks.apdus[0].p1, 0x00);
+        assert_eq!(chunks.apdus[1].p1, 0x80);
+        assert_eq!(chunks.apdus[0].data[..21], path().serialize());
+        assert_eq!(reassemble(&chunks), tx);
+    }
+
+    #[test]
+    fn keeps_vrs_with_the_previous_chunk() {
+        // The first chunk fits 129 bytes of transaction, which is where `v, r, s` starts
+        let tx = legacy_tx(&[0xaa; 98]);
+        assert_eq!(rlp::list_items(&tx).unwrap()[6].offset, CHUNK_SIZE - 21);
+
+        let chunks = sign_transaction_apdus(&path(), &tx).unwrap();
+        assert_eq!(chunks.kind, TransactionKind::Legacy { chain_id: Some(1) });
+        assert_eq!(chunks.apdus.len(), 1);
+        assert_eq!(reassemble(&chunks), tx);
+    }
+
+    #[test]
+    fn chunks_long_legacy_transaction() {
+        let tx = legacy_tx(&[0xaa; 200]);
+        let chunks = sign_transaction_apdus(&path(), &tx).unwrap();
+
+        assert_eq!(chunks.apdus.len(), 2);
+        assert_eq!(reassemble(&chunks), tx);
+    }
+
+    #[test]
+    fn rejects_malformed_transaction() {
+        assert!(sign_transaction_apdus(&path(), &[]).is_err());
+        assert!(sign_transaction_apdus(&path(), &[0xc3, 0x01]).is_err());
+    }
+
+    #[test]
+    fn rebuilds_v() {
+        let mainnet = TransactionKind::Legacy { chain_id: Some(1) };
+        assert_eq!(mainnet.v(37), Ok(37));
+        assert_eq!(mainnet.v(38), Ok(38));
+
+        // 137 * 2 + 35 = 309, answered as 309 % 256 = 53
+        let polygon = TransactionKind::Legacy {
+            chain_id: Some(137),
+        };
+        assert_eq!(polygon.v(53), Ok(309));
+        assert_eq!(polygon.v(54), Ok(310));
+
+        let unprotected = TransactionKind::Legacy { chain_id: None };
+        assert_eq!(unprotected.v(27), Ok(27));
+        assert_eq!(TransactionKind::Typed(0x02).v(1), Ok(1));
+    }
+
+    #[test]
+    fn v_overflow_is_an_error() {
+        let kind = TransactionKind::Legacy {
+            chain_id: Some(u64::MAX / 2),
+        };
+        assert!(kind.v(0).is_err());
+    }
+}
@@ -1,30 +1,56 @@
+use super::launcher::{AppSwitch, SwitchStep};
 use crate::{
-    apdu::APDUCommand, constant::*, device::Device, event::general::*, transport::Transport,
+    apdu::{APDUAnswer, APDUCommand},
+    constant::*,
+    device::{
+        BatteryState, Device, DeviceName, DeviceVersion, InstalledApp, InstalledApps, LockState,
+        RunningApp, SelectedDevice,
+    },
+    error::{APDUAnswerError, APDUErrorCode},
+    event::{
+        general::*,
+        queue::{CommandCancelled, CommandCompleted},
+    },
+    queue::{CommandId, CommandQueue, Interactive, QueuedCommand},
 };
-use bevy::{log, prelude::*};
+use bevy::{log, prelude::*, utils::HashMap};
 use hidapi::HidApi;
+use std::sync::{Arc, Mutex};
 
 pub struct GeneralPlugin;
 
 impl Plugin for GeneralPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<ScanDevices>()
+        app.init_resource::<SelectedDevice>()
+            .init_resource::<PendingAppLists>()
+            .add_event::<ScanDevices>()
+            .add_event::<DevicesScanned>()
             .add_event::<GetVersion>()
+            .add_event::<VersionReceived>()
             .add_event::<GetAppAndVersion>()
+            .add_event::<AppAndVersionReceived>()
             .add_event::<ListApps>()
+            .add_event::<AppsListed>()
             .add_event::<OpenApp>()
             .add_event::<QuitApp>()
             .add_event::<GetDeviceName>()
+            .add_event::<DeviceNameReceived>()
             .add_event::<EditDeviceName>()
             .add_event::<UninstallLanguage>()
             .add_event::<StaxFetchImageSize>()
             .add_event::<GetBatteryState>()
+            .add_event::<BatteryStateReceived>()
+            .add_event::<AnswerMalformed>()
+            .add_event::<SendRawApdu>()
+            .add_event::<RawApduAnswered>()
             .add_systems((
                 scan_devices,
                 log_added_devices,
+                select_default_device,
                 get_version,
                 open_app,
                 get_app_and_version,
+                track_running_app,
                 list_apps,
                 quit_app,
                 get_device_name,
@@ -32,126 +58,383 @@ impl Plugin for GeneralPlugin {
                 uninstall_language,
                 stax_fetch_image_size,
                 get_battery_state,
+            ))
+            .add_systems((
+                send_raw_apdu,
+                on_device_answers,
+                on_battery_state_answers,
+                on_app_list_answers,
+                forget_app_lists,
             ));
     }
 }
 
-fn scan_devices(mut events: EventReader<ScanDevices>, mut commands: Commands) {
+fn scan_devices(
+    mut events: EventReader<ScanDevices>,
+    mut devices: Query<(Entity, &Device, Option<&mut AppSwitch>)>,
+    mut finished: EventWriter<DevicesScanned>,
+    mut commands: Commands,
+) {
     events.iter().for_each(|_e| {
         log::info!("Scanning devices");
 
         let api = HidApi::new().unwrap();
-        let mut is_empty = true;
+        let scanned: Vec<Device> = api
+            .device_list()
+            .map(|d| Device::from(d.clone()))
+            .filter(Device::is_ledger)
+            .collect();
+
+        if scanned.is_empty() {
+            log::info!("Cannot find any Ledger devices. Make sure your device is connected.");
+        }
+
+        devices.for_each_mut(|(device_id, device, switch)| {
+            // Devices not reached through USB aren't enumerated
+            if device.path().is_none() || scanned.iter().any(|d| d.path() == device.path()) {
+                return;
+            }
 
-        api.device_list().for_each(|d| {
-            let device = Device::from(d.clone());
-            if device.is_ledger() {
-                is_empty = false;
-                commands.spawn(device);
+            // Devices switching apps re-enumerate, keep them until they come back
+            match switch {
+                Some(mut switch) if switch.is_reconnecting() => {
+                    if let SwitchStep::Reconnecting { gone, .. } = &mut switch.step {
+                        *gone = true;
+                    }
+                }
+                _ => {
+                    log::info!("Device removed: {device}");
+                    commands.entity(device_id).despawn();
+                }
             }
         });
 
-        if is_empty {
-            log::info!("Cannot find any Ledger devices. Make sure your device is connected.");
-        }
+        let added: Vec<Device> = scanned
+            .into_iter()
+            .filter(|d| {
+                !devices
+                    .iter()
+                    .any(|(_, device, _)| d.path() == device.path())
+            })
+            .collect();
+
+        added.into_iter().for_each(|device| {
+            // A device without serial number cannot be told apart from another one of the same model
+            let reconnecting = device.serial_number().and_then(|serial| {
+                devices.iter_mut().find(|(_, d, switch)| {
+                    d.serial_number() == Some(serial)
+                        && switch.as_ref().is_some_and(|s| s.is_gone())
+                })
+            });
+
+            match reconnecting {
+                Some((device_id, _, Some(mut switch))) => {
+                    log::info!("Device reconnected: {device}");
+                    switch.step = SwitchStep::Reconnected;
+                    commands.entity(device_id).insert(device);
+                }
+                _ => {
+                    commands.spawn((device, CommandQueue::default(), LockState::default()));
+                }
+            }
+        });
+
+        finished.send(DevicesScanned);
     });
 }
 
 fn log_added_devices(query: Query<&Device, Added<Device>>) {
     query.iter().for_each(|d| {
-        log::info!("New device added: {d}");
+        log::info!("New device added: {} {d}", d.model());
     });
 }
 
+/// Select the first available device whenever the selected one is gone
+fn select_default_device(
+    mut selected: ResMut<SelectedDevice>,
+    devices: Query<Entity, With<Device>>,
+) {
+    if selected.0.is_some_and(|id| devices.contains(id)) {
+        return;
+    }
+
+    let first = devices.iter().next();
+    if selected.0 != first {
+        selected.0 = first;
+    }
+}
+
 // Todo: Ledger device: communication error `response was too short`
-fn get_version(mut events: EventReader<GetVersion>, query: Query<(Entity, &Device)>) {
+fn get_version(mut events: EventReader<GetVersion>, mut queues: Query<&mut CommandQueue>) {
     events.iter().for_each(|e| {
-        query.iter().for_each(|(device_id, device)| {
-            if e.device_id == device_id {
-                match Transport::open(&device) {
-                    Ok(t) => {
-                        let cmd = APDUCommand {
-                            cla: CLA_DEVICE_INFO,
-                            ins: INS_DEVICE_INFO,
-                            p1: 0x00,
-                            p2: 0x00,
-                            data: Vec::<u8>::new(),
-                        };
-
-                        match t.exchange(cmd) {
-                            Ok(res) => {
-                                log::info!("{res:?}");
-                            }
-                            Err(e) => {
-                                log::error!("{e}");
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("{e}");
-                    }
-                }
-            }
-        });
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot get version: unknown device");
+            return;
+        };
+
+        let cmd = APDUCommand {
+            cla: CLA_DEVICE_INFO,
+            ins: INS_DEVICE_INFO,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::<u8>::new(),
+        };
+
+        queue.push(QueuedCommand::new(GET_VERSION, cmd).idempotent());
     });
 }
 
-fn get_app_and_version(mut events: EventReader<GetAppAndVersion>) {
-    events.iter().for_each(|_e| {
-        todo!();
-    })
+fn get_app_and_version(
+    mut events: EventReader<GetAppAndVersion>,
+    mut queues: Query<&mut CommandQueue>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot get app and version: unknown device");
+            return;
+        };
+
+        queue.push(app_and_version_command());
+    });
 }
 
-fn list_apps(mut events: EventReader<ListApps>) {
-    events.iter().for_each(|_e| {
-        todo!();
-    })
+pub(crate) fn app_and_version_command() -> QueuedCommand {
+    let cmd = APDUCommand {
+        cla: CLA_APP_AND_VERSION,
+        ins: INS_APP_AND_VERSION,
+        p1: 0x00,
+        p2: 0x00,
+        data: Vec::<u8>::new(),
+    };
+
+    QueuedCommand::new(GET_APP_AND_VERSION, cmd).idempotent()
 }
 
-// Todo: Ledger device: communication error `response was too short`
-fn open_app(mut events: EventReader<OpenApp>, query: Query<(Entity, &Device)>) {
+/// Keep `RunningApp` up to date from the answers of the commands querying or changing it
+fn track_running_app(
+    mut events: EventReader<CommandCompleted>,
+    mut received: EventWriter<AppAndVersionReceived>,
+    mut malformed: EventWriter<AnswerMalformed>,
+    mut commands: Commands,
+) {
     events.iter().for_each(|e| {
-        query.iter().for_each(|(device_id, device)| {
-            if e.device_id == device_id {
-                match Transport::open(&device) {
-                    Ok(t) => {
-                        let cmd = APDUCommand {
-                            cla: CLA_OPEN_APP,
-                            ins: INS_OPEN_APP,
-                            p1: 0x00,
-                            p2: 0x00,
-                            data: Vec::from(e.name.as_bytes()),
-                        };
-
-                        match t.exchange(cmd) {
-                            Ok(res) => {
-                                // Todo: Parse APDUAnswer
-                                log::info!("{res:?}");
-                            }
-                            Err(e) => {
-                                log::error!("{e}");
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("{e}");
+        let Ok(answer) = &e.result else {
+            return;
+        };
+        let Some(mut device) = commands.get_entity(e.device_id) else {
+            return;
+        };
+
+        match (e.name, answer.error_code()) {
+            (GET_APP_AND_VERSION | HEARTBEAT, Ok(APDUErrorCode::NoError)) => {
+                let Some(app) = RunningApp::from_answer(answer.data()) else {
+                    log::error!("{} {}: malformed app and version", e.name, e.command_id);
+                    if e.name == GET_APP_AND_VERSION {
+                        malformed.send(AnswerMalformed {
+                            device_id: e.device_id,
+                            command_id: e.command_id,
+                            name: e.name,
+                        });
                     }
+                    return;
+                };
+
+                device.insert(app.clone());
+                if e.name == GET_APP_AND_VERSION {
+                    received.send(AppAndVersionReceived {
+                        device_id: e.device_id,
+                        command_id: e.command_id,
+                        app,
+                    });
                 }
             }
+            // The running app changed, or isn't the one we thought
+            (OPEN_APP | QUIT_APP, Ok(APDUErrorCode::NoError))
+            | (_, Ok(APDUErrorCode::ClaNotSupported)) => {
+                device.remove::<RunningApp>();
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Apps listed so far by each `ListApps` command
+#[derive(Resource, Default)]
+struct PendingAppLists(HashMap<CommandId, PendingAppList>);
+
+struct PendingAppList {
+    device_id: Entity,
+    apps: Arc<Mutex<Vec<InstalledApp>>>,
+}
+
+fn list_apps(
+    mut events: EventReader<ListApps>,
+    mut queues: Query<&mut CommandQueue>,
+    mut pending: ResMut<PendingAppLists>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot list apps: unknown device");
+            return;
+        };
+
+        let listing = AppListing::default();
+        let apps = listing.apps.clone();
+        let cmd = APDUCommand {
+            cla: CLA_LIST_APPS,
+            ins: INS_LIST_APPS,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::<u8>::new(),
+        };
+
+        let command = QueuedCommand::new(LIST_APPS, cmd)
+            .with_user_confirmation()
+            .with_interactive(listing);
+        let device_id = e.device_id;
+        pending
+            .0
+            .insert(queue.push(command), PendingAppList { device_id, apps });
+    });
+}
+
+/// Asks for the next apps until the device answers an empty list
+#[derive(Default)]
+struct AppListing {
+    apps: Arc<Mutex<Vec<InstalledApp>>>,
+}
+
+impl Interactive for AppListing {
+    fn respond(
+        &mut self,
+        answer: &APDUAnswer<Vec<u8>>,
+    ) -> eyre::Result<Option<APDUCommand<Vec<u8>>>> {
+        if answer.error_code() != Ok(APDUErrorCode::NoError) || answer.data().is_empty() {
+            return Ok(None);
+        }
+
+        let apps = InstalledApp::from_answer(answer.data())
+            .ok_or(APDUAnswerError::Malformed("app list"))?;
+        self.apps.lock().unwrap().extend(apps);
+
+        Ok(Some(APDUCommand {
+            cla: CLA_LIST_APPS,
+            ins: INS_LIST_APPS_CONTINUE,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::new(),
+        }))
+    }
+}
+
+/// Insert the apps listed once the device answered an empty list
+fn on_app_list_answers(
+    mut events: EventReader<CommandCompleted>,
+    mut pending: ResMut<PendingAppLists>,
+    mut apps_listed: EventWriter<AppsListed>,
+    mut commands: Commands,
+) {
+    events.iter().filter(|e| e.name == LIST_APPS).for_each(|e| {
+        let Some(list) = pending.0.remove(&e.command_id) else {
+            return;
+        };
+        let Ok(answer) = &e.result else {
+            return;
+        };
+        if answer.error_code() != Ok(APDUErrorCode::NoError) {
+            return;
+        }
+
+        let apps = std::mem::take(&mut *list.apps.lock().unwrap());
+        if let Some(mut device) = commands.get_entity(e.device_id) {
+            device.insert(InstalledApps(apps.clone()));
+        }
+        apps_listed.send(AppsListed {
+            device_id: e.device_id,
+            command_id: e.command_id,
+            apps,
         });
     });
 }
 
-fn quit_app(mut events: EventReader<QuitApp>) {
-    events.iter().for_each(|_e| {
-        todo!();
-    })
+/// Drop the listings that will never complete, their command got cancelled or their device removed
+fn forget_app_lists(
+    mut cancelled: EventReader<CommandCancelled>,
+    mut removed: RemovedComponents<Device>,
+    mut pending: ResMut<PendingAppLists>,
+) {
+    cancelled.iter().for_each(|e| {
+        pending.0.remove(&e.command_id);
+    });
+    removed.iter().for_each(|device_id| {
+        pending.0.retain(|_, list| list.device_id != device_id);
+    });
 }
 
-fn get_device_name(mut events: EventReader<GetDeviceName>) {
-    events.iter().for_each(|_e| {
-        todo!();
-    })
+// Todo: Ledger device: communication error `response was too short`
+fn open_app(mut events: EventReader<OpenApp>, mut queues: Query<&mut CommandQueue>) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot open app: unknown device");
+            return;
+        };
+
+        queue.push(open_app_command(&e.name));
+    });
+}
+
+pub(crate) fn open_app_command(name: &str) -> QueuedCommand {
+    let cmd = APDUCommand {
+        cla: CLA_OPEN_APP,
+        ins: INS_OPEN_APP,
+        p1: 0x00,
+        p2: 0x00,
+        data: Vec::from(name.as_bytes()),
+    };
+
+    QueuedCommand::new(OPEN_APP, cmd).with_user_confirmation()
+}
+
+fn quit_app(mut events: EventReader<QuitApp>, mut queues: Query<&mut CommandQueue>) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot quit app: unknown device");
+            return;
+        };
+
+        queue.push(quit_app_command());
+    });
+}
+
+pub(crate) fn quit_app_command() -> QueuedCommand {
+    let cmd = APDUCommand {
+        cla: CLA_QUIT_APP,
+        ins: INS_QUIT_APP,
+        p1: 0x00,
+        p2: 0x00,
+        data: Vec::<u8>::new(),
+    };
+
+    QueuedCommand::new(QUIT_APP, cmd)
+}
+
+fn get_device_name(mut events: EventReader<GetDeviceName>, mut queues: Query<&mut CommandQueue>) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot get device name: unknown device");
+            return;
+        };
+
+        let cmd = APDUCommand {
+            cla: CLA_DEVICE_NAME,
+            ins: INS_GET_DEVICE_NAME,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::<u8>::new(),
+        };
+
+        queue.push(QueuedCommand::new(GET_DEVICE_NAME, cmd).with_user_confirmation());
+    });
 }
 
 fn edit_device_name(mut events: EventReader<EditDeviceName>) {
@@ -172,8 +455,243 @@ fn stax_fetch_image_size(mut events: EventReader<StaxFetchImageSize>) {
     })
 }
 
-fn get_battery_state(mut events: EventReader<GetBatteryState>) {
-    events.iter().for_each(|_e| {
-        todo!();
-    })
+fn get_battery_state(
+    mut events: EventReader<GetBatteryState>,
+    mut queues: Query<&mut CommandQueue>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot get battery state: unknown device");
+            return;
+        };
+
+        let cmd = APDUCommand {
+            cla: CLA_BATTERY_STATE,
+            ins: INS_GET_BATTERY_STATE,
+            p1: 0x00,
+            p2: P2_BATTERY_PERCENTAGE,
+            data: Vec::<u8>::new(),
+        };
+
+        queue.push(QueuedCommand::new(GET_BATTERY_STATE, cmd).idempotent());
+    });
+}
+
+fn send_raw_apdu(mut events: EventReader<SendRawApdu>, mut queues: Query<&mut CommandQueue>) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot send raw APDU: unknown device");
+            return;
+        };
+
+        queue.push(QueuedCommand::new(RAW_APDU, e.apdu.clone()));
+    });
+}
+
+/// Decode the answers to the device commands other than the ones tracking the running app
+fn on_device_answers(
+    mut events: EventReader<CommandCompleted>,
+    mut version_received: EventWriter<VersionReceived>,
+    mut name_received: EventWriter<DeviceNameReceived>,
+    mut malformed: EventWriter<AnswerMalformed>,
+    mut raw_answered: EventWriter<RawApduAnswered>,
+    mut commands: Commands,
+) {
+    events.iter().for_each(|e| {
+        if e.name == RAW_APDU {
+            raw_answered.send(RawApduAnswered {
+                device_id: e.device_id,
+                command_id: e.command_id,
+                result: e.result.as_ref().cloned().map_err(|err| err.to_string()),
+            });
+            return;
+        }
+
+        let Ok(answer) = &e.result else {
+            return;
+        };
+        if answer.error_code() != Ok(APDUErrorCode::NoError) {
+            return;
+        }
+
+        match e.name {
+            GET_VERSION => {
+                let Some(version) = DeviceVersion::from_answer(answer.data()) else {
+                    log::error!("{} {}: malformed version", e.name, e.command_id);
+                    malformed.send(AnswerMalformed {
+                        device_id: e.device_id,
+                        command_id: e.command_id,
+                        name: e.name,
+                    });
+                    return;
+                };
+
+                if let Some(mut device) = commands.get_entity(e.device_id) {
+                    device.insert(version.clone());
+                }
+                version_received.send(VersionReceived {
+                    device_id: e.device_id,
+                    command_id: e.command_id,
+                    version,
+                });
+            }
+            GET_DEVICE_NAME => {
+                let name = String::from_utf8_lossy(answer.data()).into_owned();
+                if let Some(mut device) = commands.get_entity(e.device_id) {
+                    device.insert(DeviceName(name.clone()));
+                }
+                name_received.send(DeviceNameReceived {
+                    device_id: e.device_id,
+                    command_id: e.command_id,
+                    name,
+                });
+            }
+            _ => {}
+        }
+    });
+}
+
+fn on_battery_state_answers(
+    mut events: EventReader<CommandCompleted>,
+    mut received: EventWriter<BatteryStateReceived>,
+    mut malformed: EventWriter<AnswerMalformed>,
+    mut commands: Commands,
+) {
+    events
+        .iter()
+        .filter(|e| e.name == GET_BATTERY_STATE)
+        .for_each(|e| {
+            let Ok(answer) = &e.result else {
+                return;
+            };
+            if answer.error_code() != Ok(APDUErrorCode::NoError) {
+                return;
+            }
+
+            let Some(state) = BatteryState::from_answer(answer.data()) else {
+                log::error!("{} {}: malformed battery state", e.name, e.command_id);
+                malformed.send(AnswerMalformed {
+                    device_id: e.device_id,
+                    command_id: e.command_id,
+                    name: e.name,
+                });
+                return;
+            };
+
+            if let Some(mut device) = commands.get_entity(e.device_id) {
+                device.insert(state);
+            }
+            received.send(BatteryStateReceived {
+                device_id: e.device_id,
+                command_id: e.command_id,
+                state,
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_event::<CommandCompleted>()
+            .add_event::<CommandCancelled>()
+            .add_plugin(GeneralPlugin);
+        let device = app
+            .world
+            .spawn((
+                Device::tcp("127.0.0.1:9999".parse().unwrap()),
+                CommandQueue::default(),
+            ))
+            .id();
+        (app, device)
+    }
+
+    /// List the apps of `device_id`, returning the apps listed so far and the command id
+    fn list(app: &mut App, device_id: Entity) -> (CommandId, Arc<Mutex<Vec<InstalledApp>>>) {
+        app.world.send_event(ListApps { device_id });
+        app.update();
+
+        let pending = app.world.resource::<PendingAppLists>();
+        let (id, list) = pending.0.iter().last().unwrap();
+        (*id, list.apps.clone())
+    }
+
+    /// Payload of a chunk of the list holding one app
+    fn chunk(name: &str) -> Vec<u8> {
+        let mut entry = vec![0x00, 0x02, 0x08, 0x00];
+        entry.extend([0x11; 32]);
+        entry.extend([0x22; 32]);
+        entry.push(name.len() as u8);
+        entry.extend(name.as_bytes());
+
+        let mut data = vec![0x01, entry.len() as u8];
+        data.extend(entry);
+        data
+    }
+
+    #[test]
+    fn lists_apps_until_an_empty_chunk() {
+        let mut listing = AppListing::default();
+        let mut respond = |data: Vec<u8>| {
+            let answer = APDUAnswer::from_answer([data, vec![0x90, 0x00]].concat()).unwrap();
+            listing.respond(&answer).unwrap().map(|apdu| apdu.ins)
+        };
+
+        assert_eq!(respond(chunk("Bitcoin")), Some(INS_LIST_APPS_CONTINUE));
+        assert_eq!(respond(chunk("Ethereum")), Some(INS_LIST_APPS_CONTINUE));
+        assert_eq!(respond(vec![]), None);
+
+        let apps = listing.apps.lock().unwrap();
+        let names: Vec<_> = apps.iter().map(|app| app.name.as_str()).collect();
+        assert_eq!(names, ["Bitcoin", "Ethereum"]);
+        assert_eq!((apps[0].blocks, apps[0].flags), (2, 0x0800));
+    }
+
+    #[test]
+    fn inserts_listed_apps() {
+        let (mut app, device) = app();
+        let (id, apps) = list(&mut app, device);
+        *apps.lock().unwrap() = InstalledApp::from_answer(&chunk("Solana")).unwrap();
+        app.world.send_event(CommandCompleted {
+            device_id: device,
+            command_id: id,
+            name: LIST_APPS,
+            result: APDUAnswer::from_answer(vec![0x90, 0x00]),
+            timeout: Duration::from_secs(1),
+            attempts: 2,
+        });
+        app.update();
+
+        let installed = app.world.get::<InstalledApps>(device).unwrap();
+        assert_eq!(installed.0.len(), 1);
+        assert_eq!(installed.0[0].name, "Solana");
+        assert!(app.world.resource::<PendingAppLists>().0.is_empty());
+    }
+
+    #[test]
+    fn forgets_cancelled_listings() {
+        let (mut app, device) = app();
+        let (id, _) = list(&mut app, device);
+        app.world.send_event(CommandCancelled {
+            device_id: device,
+            command_id: id,
+            name: LIST_APPS,
+        });
+        app.update();
+
+        assert!(app.world.resource::<PendingAppLists>().0.is_empty());
+    }
+
+    #[test]
+    fn forgets_listings_of_removed_devices() {
+        let (mut app, device) = app();
+        list(&mut app, device);
+        app.world.despawn(device);
+        app.update();
+
+        assert!(app.world.resource::<PendingAppLists>().0.is_empty());
+    }
 }
@@ -0,0 +1,422 @@
+//! Open the app required by the next queued command: quit the running app, open the required one and wait for the device to come back
+
+use super::general::{app_and_version_command, open_app_command, quit_app_command};
+use crate::{
+    config::LedgerConfig,
+    device::RunningApp,
+    error::APDUErrorCode,
+    event::{
+        general::{AppOpenFailed, ScanDevices},
+        queue::{CommandCancelled, CommandCompleted},
+    },
+    queue::{CommandId, CommandQueue, Priority},
+};
+use bevy::{log, prelude::*};
+use std::time::Duration;
+
+/// How long to wait for the device to disconnect after switching apps, before assuming it won't
+const REENUMERATION_GRACE: Duration = Duration::from_secs(2);
+/// How often to scan for the device while waiting for it to come back
+const SCAN_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct LauncherPlugin;
+
+impl Plugin for LauncherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AppOpenFailed>().add_systems((
+            start_app_switches,
+            advance_app_switches,
+            wait_for_reconnection,
+            fail_app_switches,
+        ));
+    }
+}
+
+/// Progress of switching a device to the app required by its next command
+#[derive(Component)]
+pub(crate) struct AppSwitch {
+    pub app: &'static str,
+    pub step: SwitchStep,
+    /// Whether the app got opened already, so that it isn't opened again when the device still runs another app
+    pub opened: bool,
+}
+
+pub(crate) enum SwitchStep {
+    Querying(CommandId),
+    Quitting(CommandId),
+    Opening(CommandId),
+    /// Waiting for the device to re-enumerate after the running app changed
+    Reconnecting {
+        since: Duration,
+        gone: bool,
+    },
+    /// The device re-enumerated, the running app must be checked again
+    Reconnected,
+    /// The app cannot be opened, the commands requiring it get cancelled
+    Failed {
+        reason: String,
+        /// Whether the device is gone for good
+        gone: bool,
+    },
+}
+
+impl AppSwitch {
+    /// Whether the device cannot take commands until it comes back
+    pub fn is_reconnecting(&self) -> bool {
+        matches!(
+            self.step,
+            SwitchStep::Reconnecting { .. } | SwitchStep::Reconnected
+        )
+    }
+
+    /// Whether the device disconnected and is expected to come back
+    pub fn is_gone(&self) -> bool {
+        matches!(self.step, SwitchStep::Reconnecting { gone: true, .. })
+    }
+}
+
+/// Start switching idle devices whose next command requires an app other than the running one
+fn start_app_switches(
+    mut devices: Query<(Entity, &mut CommandQueue, Option<&RunningApp>), Without<AppSwitch>>,
+    mut commands: Commands,
+) {
+    devices.for_each_mut(|(device_id, mut queue, running)| {
+        if queue.is_busy() {
+            return;
+        }
+        let Some(app) = queue.pending().next().and_then(|c| c.app) else {
+            return;
+        };
+
+        let step = match running {
+            Some(running) if running.name == app => return,
+            Some(running) => switch_from(running, app, &mut queue),
+            None => SwitchStep::Querying(
+                queue.push(app_and_version_command().with_priority(Priority::High)),
+            ),
+        };
+
+        log::info!("Opening the {app} app");
+        commands.entity(device_id).insert(AppSwitch {
+            app,
+            step,
+            opened: false,
+        });
+    });
+}
+
+/// Command bringing the device closer to running `app`
+fn switch_from(running: &RunningApp, app: &'static str, queue: &mut CommandQueue) -> SwitchStep {
+    if running.is_dashboard() {
+        SwitchStep::Opening(queue.push(open_app_command(app).with_priority(Priority::High)))
+    } else {
+        SwitchStep::Quitting(queue.push(quit_app_command().with_priority(Priority::High)))
+    }
+}
+
+fn advance_app_switches(
+    time: Res<Time>,
+    mut events: EventReader<CommandCompleted>,
+    mut devices: Query<(&mut CommandQueue, &mut AppSwitch)>,
+    mut commands: Commands,
+) {
+    events.iter().for_each(|e| {
+        let Ok((mut queue, mut switch)) = devices.get_mut(e.device_id) else {
+            return;
+        };
+        let expected = match switch.step {
+            SwitchStep::Querying(id) | SwitchStep::Quitting(id) | SwitchStep::Opening(id) => id,
+            _ => return,
+        };
+        if e.command_id != expected {
+            return;
+        }
+
+        let code = match &e.result {
+            Ok(answer) => answer.error_code(),
+            Err(err) => {
+                let reason = err.to_string();
+                switch.step = SwitchStep::Failed {
+                    reason,
+                    gone: false,
+                };
+                return;
+            }
+        };
+        if code != Ok(APDUErrorCode::NoError) {
+            let reason = match code {
+                Ok(code) if code.is_user_rejection() => "the user rejected it".to_string(),
+                code => format!("{code:?}"),
+            };
+            switch.step = SwitchStep::Failed {
+                reason,
+                gone: false,
+            };
+            return;
+        }
+
+        let reconnecting = SwitchStep::Reconnecting {
+            since: time.elapsed(),
+            gone: false,
+        };
+        switch.step = match switch.step {
+            SwitchStep::Querying(_) => {
+                let running = e
+                    .result
+                    .as_ref()
+                    .ok()
+                    .and_then(|answer| RunningApp::from_answer(answer.data()));
+                match running {
+                    Some(running) if running.name == switch.app => {
+                        log::info!("The {} app is open", switch.app);
+                        commands.entity(e.device_id).remove::<AppSwitch>();
+                        return;
+                    }
+                    Some(running) if !switch.opened => {
+                        switch_from(&running, switch.app, &mut queue)
+                    }
+                    Some(running) => {
+                        let reason = format!("the device still runs {}", running.name);
+                        switch.step = SwitchStep::Failed {
+                            reason,
+                            gone: false,
+                        };
+                        return;
+                    }
+                    None => {
+                        let reason = "malformed app and version".to_string();
+                        switch.step = SwitchStep::Failed {
+                            reason,
+                            gone: false,
+                        };
+                        return;
+                    }
+                }
+            }
+            SwitchStep::Quitting(_) => reconnecting,
+            SwitchStep::Opening(_) => {
+                switch.opened = true;
+                reconnecting
+            }
+            _ => return,
+        };
+    });
+}
+
+/// Rescan while devices re-enumerate, checking the running app again once they're back
+fn wait_for_reconnection(
+    time: Res<Time>,
+    config: Res<LedgerConfig>,
+    mut since_scan: Local<Duration>,
+    mut devices: Query<(&mut CommandQueue, &mut AppSwitch)>,
+    mut scan: EventWriter<ScanDevices>,
+) {
+    let mut reconnecting = false;
+
+    devices.for_each_mut(|(mut queue, mut switch)| match switch.step {
+        SwitchStep::Reconnected => {
+            let id = queue.push(app_and_version_command().with_priority(Priority::High));
+            switch.step = SwitchStep::Querying(id);
+        }
+        SwitchStep::Reconnecting { since, gone } => {
+            let elapsed = time.elapsed() - since;
+            if !gone && elapsed > REENUMERATION_GRACE {
+                switch.step = SwitchStep::Reconnected;
+            } else if gone && elapsed > config.app_switch_timeout {
+                switch.step = SwitchStep::Failed {
+                    reason: "the device did not come back".to_string(),
+                    gone: true,
+                };
+            } else {
+                reconnecting = true;
+            }
+        }
+        _ => {}
+    });
+
+    *since_scan += time.delta();
+    if reconnecting && *since_scan >= SCAN_INTERVAL {
+        *since_scan = Duration::ZERO;
+        scan.send(ScanDevices);
+    }
+}
+
+/// Give up switching apps, cancelling the commands which required the app
+fn fail_app_switches(
+    mut devices: Query<(Entity, &mut CommandQueue, &AppSwitch)>,
+    mut commands: Commands,
+    mut cancelled: EventWriter<CommandCancelled>,
+    mut failed: EventWriter<AppOpenFailed>,
+) {
+    devices.for_each_mut(|(device_id, mut queue, switch)| {
+        let SwitchStep::Failed { reason, gone } = &switch.step else {
+            return;
+        };
+        log::error!("Cannot open the {} app: {reason}", switch.app);
+
+        cancelled.send_batch(
+            queue
+                .drain_app(switch.app)
+                .into_iter()
+                .map(|c| CommandCancelled {
+                    device_id,
+                    command_id: c.id,
+                    name: c.name,
+                }),
+        );
+        failed.send(AppOpenFailed {
+            device_id,
+            app: switch.app,
+            reason: reason.clone(),
+        });
+
+        if *gone {
+            log::info!("Device removed");
+            commands.entity(device_id).despawn();
+        } else {
+            commands.entity(device_id).remove::<AppSwitch>();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apdu::{APDUAnswer, APDUCommand},
+        queue::QueuedCommand,
+    };
+    use bevy::{ecs::event::ManualEventReader, utils::Instant};
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<LedgerConfig>()
+            .add_event::<CommandCompleted>()
+            .add_event::<CommandCancelled>()
+            .add_event::<ScanDevices>()
+            .add_plugin(LauncherPlugin);
+        let device = app.world.spawn(CommandQueue::default()).id();
+        (app, device)
+    }
+
+    fn command(name: &'static str) -> QueuedCommand {
+        let cmd = APDUCommand {
+            cla: 0xe0,
+            ins: 0x02,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::new(),
+        };
+        QueuedCommand::new(name, cmd)
+    }
+
+    fn step(app: &App, device: Entity) -> Option<&'static str> {
+        let switch = app.world.get::<AppSwitch>(device)?;
+        Some(match switch.step {
+            SwitchStep::Querying(_) => "Querying",
+            SwitchStep::Quitting(_) => "Quitting",
+            SwitchStep::Opening(_) => "Opening",
+            SwitchStep::Reconnecting { .. } => "Reconnecting",
+            SwitchStep::Reconnected => "Reconnected",
+            SwitchStep::Failed { .. } => "Failed",
+        })
+    }
+
+    /// Answer the command the switch is waiting for
+    fn answer(app: &mut App, device_id: Entity, mut answer: Vec<u8>, status_word: u16) {
+        let command_id = match app.world.get::<AppSwitch>(device_id).unwrap().step {
+            SwitchStep::Querying(id) | SwitchStep::Quitting(id) | SwitchStep::Opening(id) => id,
+            _ => panic!("the switch isn't waiting for an answer"),
+        };
+        answer.extend(status_word.to_be_bytes());
+        app.world.send_event(CommandCompleted {
+            device_id,
+            command_id,
+            name: "Test",
+            result: APDUAnswer::from_answer(answer),
+            timeout: Duration::from_secs(1),
+            attempts: 1,
+        });
+        app.update();
+    }
+
+    /// `GetAppAndVersion` answer of a device running `name`
+    fn running(name: &str) -> Vec<u8> {
+        [
+            &[0x01, name.len() as u8],
+            name.as_bytes(),
+            &[5],
+            b"1.0.0",
+            &[1, 0],
+        ]
+        .concat()
+    }
+
+    /// Let the re-enumeration grace period pass
+    fn wait_for_device(app: &mut App) {
+        let elapsed = app.world.resource::<Time>().elapsed();
+        let now = Instant::now() + elapsed + REENUMERATION_GRACE + Duration::from_millis(1);
+        app.world.resource_mut::<Time>().update_with_instant(now);
+        app.update();
+        app.update();
+    }
+
+    #[test]
+    fn switches_to_the_required_app() {
+        let (mut app, device) = app();
+        let mut queue = app.world.get_mut::<CommandQueue>(device).unwrap();
+        queue.push(command("sign").for_app("Ethereum"));
+        app.update();
+        assert_eq!(step(&app, device), Some("Querying"));
+
+        answer(&mut app, device, running("Bitcoin"), 0x9000);
+        assert_eq!(step(&app, device), Some("Quitting"));
+        answer(&mut app, device, Vec::new(), 0x9000);
+        assert_eq!(step(&app, device), Some("Reconnecting"));
+
+        wait_for_device(&mut app);
+        assert_eq!(step(&app, device), Some("Querying"));
+        answer(&mut app, device, running("BOLOS"), 0x9000);
+        assert_eq!(step(&app, device), Some("Opening"));
+        answer(&mut app, device, Vec::new(), 0x9000);
+        assert_eq!(step(&app, device), Some("Reconnecting"));
+
+        wait_for_device(&mut app);
+        assert_eq!(step(&app, device), Some("Querying"));
+        answer(&mut app, device, running("Ethereum"), 0x9000);
+        assert_eq!(step(&app, device), None);
+    }
+
+    #[test]
+    fn failed_switch_cancels_app_commands() {
+        let (mut app, device) = app();
+        app.world.entity_mut(device).insert(RunningApp {
+            name: "Bitcoin".to_string(),
+            version: "2.1.0".to_string(),
+        });
+        let mut queue = app.world.get_mut::<CommandQueue>(device).unwrap();
+        queue.push(command("address").for_app("Ethereum"));
+        queue.push(command("version"));
+        queue.push(command("sign").for_app("Ethereum"));
+        let mut cancelled = ManualEventReader::<CommandCancelled>::default();
+        let mut failed = ManualEventReader::<AppOpenFailed>::default();
+
+        app.update();
+        assert_eq!(step(&app, device), Some("Quitting"));
+        answer(&mut app, device, Vec::new(), 0x6985);
+        app.update();
+
+        let cancelled: Vec<_> = cancelled
+            .iter(app.world.resource())
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(cancelled, ["address", "sign"]);
+        assert_eq!(failed.iter(app.world.resource()).count(), 1);
+        assert_eq!(step(&app, device), None);
+
+        let queue = app.world.get::<CommandQueue>(device).unwrap();
+        let names: Vec<_> = queue.pending().map(|c| c.name).collect();
+        assert_eq!(names, ["QuitApp", "version"]);
+    }
+}
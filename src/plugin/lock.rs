@@ -0,0 +1,230 @@
+use crate::{
+    apdu::APDUCommand,
+    config::LedgerConfig,
+    constant::*,
+    device::LockState,
+    error::APDUErrorCode,
+    event::{lock::*, queue::CommandCompleted},
+    queue::{CommandQueue, Priority, QueuedCommand},
+};
+use bevy::{log, prelude::*};
+use std::time::Duration;
+
+pub struct LockPlugin;
+
+impl Plugin for LockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DeviceLocked>()
+            .add_event::<DeviceUnlocked>()
+            .add_systems((track_lock_state, heartbeat));
+    }
+}
+
+/// Update `LockState` from the status word of every answer
+fn track_lock_state(
+    mut events: EventReader<CommandCompleted>,
+    mut devices: Query<&mut LockState>,
+    mut locked: EventWriter<DeviceLocked>,
+    mut unlocked: EventWriter<DeviceUnlocked>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(answer) = &e.result else {
+            return;
+        };
+        let Ok(mut lock_state) = devices.get_mut(e.device_id) else {
+            return;
+        };
+
+        let state = match answer.error_code() {
+            Ok(APDUErrorCode::LockedDevice | APDUErrorCode::SecurityStatusNotSatisfied) => {
+                LockState::Locked
+            }
+            Ok(APDUErrorCode::NoError) => LockState::Unlocked,
+            _ => return,
+        };
+
+        if *lock_state == state {
+            return;
+        }
+        *lock_state = state;
+
+        match state {
+            LockState::Locked => {
+                log::info!("Device locked, enter your PIN to continue");
+                locked.send(DeviceLocked {
+                    device_id: e.device_id,
+                });
+            }
+            LockState::Unlocked => {
+                log::info!("Device unlocked");
+                unlocked.send(DeviceUnlocked {
+                    device_id: e.device_id,
+                });
+            }
+            LockState::Unknown => {}
+        }
+    });
+}
+
+/// Periodically send a lightweight command to idle devices so that `LockState` follows the device
+fn heartbeat(
+    time: Res<Time>,
+    config: Res<LedgerConfig>,
+    mut elapsed: Local<Duration>,
+    mut queues: Query<&mut CommandQueue, With<LockState>>,
+) {
+    let Some(interval) = config.heartbeat_interval else {
+        return;
+    };
+
+    *elapsed += time.delta();
+    if *elapsed < interval {
+        return;
+    }
+    *elapsed = Duration::ZERO;
+
+    queues.for_each_mut(|mut queue| {
+        if queue.depth() > 0 {
+            return;
+        }
+
+        let cmd = APDUCommand {
+            cla: CLA_APP_AND_VERSION,
+            ins: INS_APP_AND_VERSION,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::<u8>::new(),
+        };
+
+        queue.push(QueuedCommand::new(HEARTBEAT, cmd).with_priority(Priority::Low));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apdu::APDUAnswer,
+        queue::{Exchanged, InFlight},
+    };
+    use bevy::tasks::TaskPool;
+
+    fn app(heartbeat_interval: Option<Duration>) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .insert_resource(LedgerConfig {
+                heartbeat_interval,
+                ..default()
+            })
+            .add_event::<CommandCompleted>()
+            .add_plugin(LockPlugin);
+        app
+    }
+
+    fn heartbeat_command() -> QueuedCommand {
+        let cmd = APDUCommand {
+            cla: CLA_APP_AND_VERSION,
+            ins: INS_APP_AND_VERSION,
+            p1: 0x00,
+            p2: 0x00,
+            data: Vec::new(),
+        };
+        QueuedCommand::new(HEARTBEAT, cmd)
+    }
+
+    fn complete(app: &mut App, device_id: Entity, status_word: u16) {
+        app.world.send_event(CommandCompleted {
+            device_id,
+            command_id: heartbeat_command().id,
+            name: HEARTBEAT,
+            result: APDUAnswer::from_answer(status_word.to_be_bytes().to_vec()),
+            timeout: Duration::from_secs(1),
+            attempts: 1,
+        });
+        app.update();
+    }
+
+    #[test]
+    fn status_words_update_lock_state() {
+        let mut app = app(None);
+        let device = app.world.spawn(LockState::default()).id();
+        let lock_state = |app: &App| *app.world.get::<LockState>(device).unwrap();
+        let mut locked = app.world.resource::<Events<DeviceLocked>>().get_reader();
+        let mut unlocked = app.world.resource::<Events<DeviceUnlocked>>().get_reader();
+        let mut events = |app: &App| {
+            (
+                locked.iter(app.world.resource()).count(),
+                unlocked.iter(app.world.resource()).count(),
+            )
+        };
+
+        complete(&mut app, device, 0x6d00);
+        assert_eq!(lock_state(&app), LockState::Unknown);
+        assert_eq!(events(&app), (0, 0));
+
+        complete(&mut app, device, 0x5515);
+        assert_eq!(lock_state(&app), LockState::Locked);
+        assert_eq!(events(&app), (1, 0));
+
+        complete(&mut app, device, 0x6982);
+        assert_eq!(lock_state(&app), LockState::Locked);
+        assert_eq!(events(&app), (0, 0));
+
+        complete(&mut app, device, 0x9000);
+        assert_eq!(lock_state(&app), LockState::Unlocked);
+        assert_eq!(events(&app), (0, 1));
+    }
+
+    #[test]
+    fn heartbeat_skips_busy_devices() {
+        let mut app = app(Some(Duration::ZERO));
+        let idle = app
+            .world
+            .spawn((LockState::default(), CommandQueue::default()))
+            .id();
+
+        let mut queue = CommandQueue::default();
+        queue.push(heartbeat_command());
+        let pending = app.world.spawn((LockState::default(), queue)).id();
+
+        let sent = heartbeat_command();
+        let mut queue = CommandQueue::default();
+        queue.in_flight = Some(InFlight {
+            id: sent.id,
+            name: sent.name,
+            timeout: Duration::from_secs(1),
+            user_confirmation: false,
+            task: TaskPool::new().spawn(async {
+                Exchanged {
+                    result: APDUAnswer::from_answer(vec![0x90, 0x00]),
+                    attempts: 1,
+                }
+            }),
+        });
+        let in_flight = app.world.spawn((LockState::default(), queue)).id();
+
+        app.update();
+
+        let queue = |device| app.world.get::<CommandQueue>(device).unwrap();
+        let idle = queue(idle).pending().collect::<Vec<_>>();
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].name, HEARTBEAT);
+        assert_eq!(idle[0].priority, Priority::Low);
+        assert_eq!(queue(pending).depth(), 1);
+        assert_eq!(queue(in_flight).depth(), 1);
+        assert_eq!(queue(in_flight).pending().count(), 0);
+    }
+
+    #[test]
+    fn heartbeat_can_be_disabled() {
+        let mut app = app(None);
+        let device = app
+            .world
+            .spawn((LockState::default(), CommandQueue::default()))
+            .id();
+
+        app.update();
+
+        assert_eq!(app.world.get::<CommandQueue>(device).unwrap().depth(), 0);
+    }
+}
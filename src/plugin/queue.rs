@@ -0,0 +1,295 @@
+use super::launcher::AppSwitch;
+use crate::{
+    apdu::{APDUAnswer, APDUCommand},
+    config::{LedgerConfig, RetryPolicy},
+    device::{Device, RunningApp},
+    error::{APDUErrorCode, DeviceHIDError},
+    event::queue::*,
+    queue::{CommandId, CommandQueue, Exchanged, InFlight, Interactive},
+    record::{ApduRecorder, RecordedExchange},
+    transport::Transport,
+};
+use bevy::{log, prelude::*, tasks::AsyncComputeTaskPool};
+use futures_lite::future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct QueuePlugin;
+
+impl Plugin for QueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LedgerConfig>()
+            .add_event::<CancelCommand>()
+            .add_event::<CommandCancelled>()
+            .add_event::<CommandCompleted>()
+            .add_event::<CommandLifecycle>()
+            .add_systems((
+                cancel_commands,
+                process_command_queues.after(cancel_commands),
+                log_completed_commands,
+            ));
+    }
+}
+
+fn cancel_commands(
+    mut events: EventReader<CancelCommand>,
+    mut queues: Query<&mut CommandQueue>,
+    mut cancelled: EventWriter<CommandCancelled>,
+) {
+    events.iter().for_each(|e| {
+        let Ok(mut queue) = queues.get_mut(e.device_id) else {
+            log::error!("Cannot cancel command {}: unknown device", e.command_id);
+            return;
+        };
+
+        match queue.cancel(e.command_id) {
+            Some(command) => cancelled.send(CommandCancelled {
+                device_id: e.device_id,
+                command_id: command.id,
+                name: command.name,
+            }),
+            None => log::warn!(
+                "Cannot cancel command {}: already sent or unknown",
+                e.command_id
+            ),
+        }
+    });
+}
+
+type DeviceQueue = (
+    Entity,
+    &'static Device,
+    &'static mut CommandQueue,
+    Option<&'static RunningApp>,
+    Option<&'static AppSwitch>,
+);
+
+/// Poll the command being exchanged with each device, and send the next one once the device is free
+fn process_command_queues(
+    config: Res<LedgerConfig>,
+    recorder: Option<Res<ApduRecorder>>,
+    mut queues: Query<DeviceQueue>,
+    mut completed: EventWriter<CommandCompleted>,
+    mut lifecycle: EventWriter<CommandLifecycle>,
+) {
+    queues.for_each_mut(|(device_id, device, mut queue, running_app, switch)| {
+        if let Some(in_flight) = queue.in_flight.as_mut() {
+            match future::block_on(future::poll_once(&mut in_flight.task)) {
+                Some(exchanged) => {
+                    let in_flight = queue.in_flight.take().unwrap();
+                    if let Some(stage) = final_stage(&exchanged, in_flight.user_confirmation) {
+                        lifecycle.send(CommandLifecycle {
+                            device_id,
+                            command_id: in_flight.id,
+                            name: in_flight.name,
+                            stage,
+                        });
+                    }
+
+                    completed.send(CommandCompleted {
+                        device_id,
+                        command_id: in_flight.id,
+                        name: in_flight.name,
+                        result: exchanged.result,
+                        timeout: in_flight.timeout,
+                        attempts: exchanged.attempts,
+                    });
+                }
+                None => return,
+            }
+        }
+
+        // The device is re-enumerating after switching apps
+        if switch.is_some_and(AppSwitch::is_reconnecting) {
+            return;
+        }
+
+        if let Some(command) = queue.pop(running_app) {
+            let device = device.clone();
+            let timeout = config.timeout_for(&command);
+            let retry = command.idempotent.then_some(config.retry);
+            let apdus = command.apdus;
+            let mut interactive = command.interactive;
+            let recording = recorder.as_deref().map(|recorder| Recording {
+                recorder: recorder.clone(),
+                device_id,
+                device: device.to_string(),
+                command: command.name,
+                command_id: command.id,
+            });
+            let task = AsyncComputeTaskPool::get().spawn(async move {
+                let recording = recording.as_ref();
+                exchange(&device, &apdus, &mut interactive, timeout, retry, recording)
+            });
+
+            let mut stages = vec![CommandStage::Sent];
+            if command.user_confirmation {
+                stages.push(CommandStage::AwaitingUserConfirmation);
+            }
+            lifecycle.send_batch(stages.into_iter().map(|stage| CommandLifecycle {
+                device_id,
+                command_id: command.id,
+                name: command.name,
+                stage,
+            }));
+
+            queue.in_flight = Some(InFlight {
+                id: command.id,
+                name: command.name,
+                timeout,
+                user_confirmation: command.user_confirmation,
+                task,
+            });
+        }
+    });
+}
+
+/// Open the device and exchange the command, retrying on transient errors when a policy is given
+fn exchange(
+    device: &Device,
+    apdus: &[APDUCommand<Vec<u8>>],
+    interactive: &mut Option<Box<dyn Interactive>>,
+    timeout: Duration,
+    retry: Option<RetryPolicy>,
+    recording: Option<&Recording>,
+) -> Exchanged {
+    let max_attempts = retry.map_or(1, |r| r.max_attempts.max(1));
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        let result = Transport::open(device)
+            .map_err(eyre::Report::from)
+            .and_then(|t| exchange_all(&t, apdus, interactive, timeout, recording));
+
+        match (&result, retry) {
+            (Err(err), Some(retry)) if attempts < max_attempts && is_transient(err) => {
+                log::warn!("{err}, retrying ({attempts}/{max_attempts})");
+                std::thread::sleep(retry.backoff);
+            }
+            _ => return Exchanged { result, attempts },
+        }
+    }
+}
+
+/// Exchange each APDU in order, stopping at the first answer which isn't a success, then answer the device's requests if any
+fn exchange_all(
+    transport: &Transport,
+    apdus: &[APDUCommand<Vec<u8>>],
+    interactive: &mut Option<Box<dyn Interactive>>,
+    timeout: Duration,
+    recording: Option<&Recording>,
+) -> eyre::Result<APDUAnswer<Vec<u8>>> {
+    let (last, chunks) = apdus
+        .split_last()
+        .ok_or_else(|| eyre::eyre!("command has no APDU"))?;
+
+    for apdu in chunks {
+        let answer = exchange_apdu(transport, apdu.clone(), timeout, recording)?;
+        if answer.error_code() != Ok(APDUErrorCode::NoError) {
+            return Ok(answer);
+        }
+    }
+
+    let mut answer = exchange_apdu(transport, last.clone(), timeout, recording)?;
+    if let Some(interactive) = interactive {
+        while let Some(apdu) = interactive.respond(&answer)? {
+            answer = exchange_apdu(transport, apdu, timeout, recording)?;
+        }
+    }
+
+    Ok(answer)
+}
+
+/// Exchange a single APDU, recording it to the session file if any
+fn exchange_apdu(
+    transport: &Transport,
+    apdu: APDUCommand<Vec<u8>>,
+    timeout: Duration,
+    recording: Option<&Recording>,
+) -> eyre::Result<APDUAnswer<Vec<u8>>> {
+    let Some(recording) = recording else {
+        return transport.exchange(apdu, timeout);
+    };
+
+    let serialized = apdu.serialize();
+    let sent_at = SystemTime::now();
+    let start = Instant::now();
+    let result = transport.exchange(apdu, timeout);
+    recording.record(sent_at, start.elapsed(), &serialized, &result);
+
+    result
+}
+
+/// Identifies the exchanges of a command in the session file
+struct Recording {
+    recorder: ApduRecorder,
+    device_id: Entity,
+    device: String,
+    command: &'static str,
+    command_id: CommandId,
+}
+
+impl Recording {
+    fn record(
+        &self,
+        sent_at: SystemTime,
+        latency: Duration,
+        apdu: &[u8],
+        result: &eyre::Result<APDUAnswer<Vec<u8>>>,
+    ) {
+        let (answer, error) = match result {
+            Ok(answer) => {
+                let raw = [answer.apdu_data(), &answer.retcode().to_be_bytes()].concat();
+                (Some(hex::encode(raw)), None)
+            }
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        self.recorder.record(&RecordedExchange {
+            timestamp_ms: sent_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |t| t.as_millis() as u64),
+            device_id: self.device_id.to_bits(),
+            device: self.device.clone(),
+            command: self.command.to_string(),
+            command_id: self.command_id,
+            apdu: hex::encode(apdu),
+            answer,
+            error,
+            latency_us: latency.as_micros() as u64,
+        });
+    }
+}
+
+/// Stage reached once the device answered, if any
+fn final_stage(exchanged: &Exchanged, user_confirmation: bool) -> Option<CommandStage> {
+    match &exchanged.result {
+        Err(err) if matches!(err.downcast_ref(), Some(DeviceHIDError::Timeout)) => {
+            Some(CommandStage::TimedOut)
+        }
+        Ok(answer) if user_confirmation => match answer.error_code() {
+            Ok(APDUErrorCode::NoError) => Some(CommandStage::UserApproved),
+            Ok(code) if code.is_user_rejection() => Some(CommandStage::UserRejected),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_transient(err: &eyre::Report) -> bool {
+    err.downcast_ref::<DeviceHIDError>()
+        .is_some_and(DeviceHIDError::is_transient)
+}
+
+fn log_completed_commands(mut events: EventReader<CommandCompleted>) {
+    events.iter().for_each(|e| match &e.result {
+        Ok(answer) => log::info!("{} {}: {answer:?}", e.name, e.command_id),
+        Err(err) if e.attempts > 1 => log::error!(
+            "{} {}: {err} after {} attempts",
+            e.name,
+            e.command_id,
+            e.attempts
+        ),
+        Err(err) => log::error!("{} {}: {err}", e.name, e.command_id),
+    });
+}
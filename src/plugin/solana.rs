@@ -0,0 +1,153 @@
+use super::{DiscoveryPlugin, LedgerAppPlugin};
+use crate::{
+    apdu::APDUCommand,
+    app::LedgerApp,
+    constant::*,
+    discovery::DiscoverableApp,
+    error::APDUAnswerError,
+    event::app::AppResponse,
+    path::DerivationPath,
+    queue::QueuedCommand,
+    solana::{SolAppConfiguration, SolCommand, SolResponse, Solana},
+};
+use bevy::prelude::*;
+
+const GET_SOL_APP_CONFIGURATION: &str = "GetSolAppConfiguration";
+const GET_SOL_PUBKEY: &str = "GetSolPubkey";
+const SIGN_SOL_MESSAGE: &str = "SignSolMessage";
+
+/// Maximum payload of an APDU
+const MAX_APDU_DATA: usize = 255;
+
+const P1_NON_CONFIRM: u8 = 0x00;
+const P1_CONFIRM: u8 = 0x01;
+
+/// Set on every chunk following the first one
+const P2_EXTEND: u8 = 0x01;
+/// Set on every chunk but the last one
+const P2_MORE: u8 = 0x02;
+
+pub struct SolanaPlugin;
+
+impl Plugin for SolanaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(LedgerAppPlugin::<Solana>::default())
+            .add_plugin(DiscoveryPlugin::<Solana>::default())
+            .add_system(insert_sol_app_configuration);
+    }
+}
+
+impl LedgerApp for Solana {
+    const NAME: &'static str = "Solana";
+    const CLA: u8 = CLA_SOL;
+    type Command = SolCommand;
+    type Response = SolResponse;
+    type State = ();
+
+    fn queued(command: &SolCommand) -> eyre::Result<(QueuedCommand, ())> {
+        let command = match command {
+            SolCommand::GetAppConfiguration => QueuedCommand::new(
+                GET_SOL_APP_CONFIGURATION,
+                Self::apdu(INS_SOL_GET_APP_CONFIGURATION, 0x00, 0x00, Vec::new()),
+            )
+            .idempotent(),
+            SolCommand::GetPubkey { path, display } => {
+                ensure_hardened(path)?;
+                let p1 = if *display { P1_CONFIRM } else { P1_NON_CONFIRM };
+                let apdu = Self::apdu(INS_SOL_GET_PUBKEY, p1, 0x00, path.serialize());
+                let command = QueuedCommand::new(GET_SOL_PUBKEY, apdu);
+                match display {
+                    true => command.with_user_confirmation(),
+                    false => command.idempotent(),
+                }
+            }
+            SolCommand::SignMessage { path, message } => {
+                ensure_hardened(path)?;
+                QueuedCommand::chunked(SIGN_SOL_MESSAGE, sign_message_apdus(path, message))
+                    .with_user_confirmation()
+            }
+        };
+
+        Ok((command, ()))
+    }
+
+    fn decode(command: &SolCommand, _: (), data: &[u8]) -> Result<SolResponse, APDUAnswerError> {
+        match command {
+            SolCommand::GetAppConfiguration => SolAppConfiguration::from_answer(data)
+                .map(SolResponse::AppConfiguration)
+                .ok_or(APDUAnswerError::Malformed("configuration is too short")),
+            SolCommand::GetPubkey { .. } => <[u8; 32]>::try_from(data)
+                .map(|pubkey| SolResponse::Pubkey {
+                    pubkey,
+                    address: bs58::encode(pubkey).into_string(),
+                })
+                .map_err(|_| APDUAnswerError::Malformed("public key is not 32 bytes long")),
+            SolCommand::SignMessage { .. } => <[u8; 64]>::try_from(data)
+                .map(SolResponse::Signature)
+                .map_err(|_| APDUAnswerError::Malformed("signature is not 64 bytes long")),
+        }
+    }
+}
+
+impl DiscoverableApp for Solana {
+    fn address_command(path: DerivationPath) -> SolCommand {
+        SolCommand::GetPubkey {
+            path,
+            display: false,
+        }
+    }
+
+    fn address(response: &SolResponse) -> Option<String> {
+        match response {
+            SolResponse::Pubkey { address, .. } => Some(address.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// ed25519 only supports hardened derivation
+fn ensure_hardened(path: &DerivationPath) -> eyre::Result<()> {
+    if !path.is_fully_hardened() {
+        eyre::bail!("ed25519 paths must be fully hardened");
+    }
+
+    Ok(())
+}
+
+/// `signers count | path | message`, split over as many APDUs as needed
+fn sign_message_apdus(path: &DerivationPath, message: &[u8]) -> Vec<APDUCommand<Vec<u8>>> {
+    let mut payload = vec![0x01];
+    payload.extend(path.serialize());
+    payload.extend(message);
+
+    let count = (payload.len() + MAX_APDU_DATA - 1) / MAX_APDU_DATA;
+    payload
+        .chunks(MAX_APDU_DATA)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut p2 = 0x00;
+            if i > 0 {
+                p2 |= P2_EXTEND;
+            }
+            if i + 1 < count {
+                p2 |= P2_MORE;
+            }
+
+            Solana::apdu(INS_SOL_SIGN_MESSAGE, P1_CONFIRM, p2, chunk.to_vec())
+        })
+        .collect()
+}
+
+/// Keep the configuration of the app on the device entity
+fn insert_sol_app_configuration(
+    mut responses: EventReader<AppResponse<Solana>>,
+    mut commands: Commands,
+) {
+    responses.iter().for_each(|e| {
+        if let Ok(SolResponse::AppConfiguration(configuration)) = e.result {
+            if let Some(mut device) = commands.get_entity(e.device_id) {
+                device.insert(configuration);
+            }
+        }
+    });
+}
@@ -0,0 +1,319 @@
+use crate::{
+    apdu::{APDUAnswer, APDUCommand},
+    device::RunningApp,
+};
+use bevy::{ecs::component::Component, tasks::Task};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Unique identifier of a command pushed to a [CommandQueue]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CommandId(u64);
+
+impl CommandId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for CommandId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Priority of a queued command. Commands with a higher priority are sent first, commands with the same priority are sent in FIFO order.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Answers the requests a device makes while processing a command, e.g. the client commands of the Bitcoin app
+pub trait Interactive: Send + Sync {
+    /// APDU to send back after the given answer, `None` once the answer completes the command
+    fn respond(
+        &mut self,
+        answer: &APDUAnswer<Vec<u8>>,
+    ) -> eyre::Result<Option<APDUCommand<Vec<u8>>>>;
+}
+
+/// A command waiting in a [CommandQueue]
+pub struct QueuedCommand {
+    pub id: CommandId,
+    /// Name of the command, used to route the answer back to the system which requested it
+    pub name: &'static str,
+    pub priority: Priority,
+    /// APDUs sent in order, e.g. the chunks of a payload too large for a single APDU. Sending stops at the first answer which isn't a success.
+    pub apdus: Vec<APDUCommand<Vec<u8>>>,
+    /// Overrides the timeout set in [LedgerConfig](crate::config::LedgerConfig)
+    pub timeout: Option<Duration>,
+    /// Whether the command can safely be sent again after a transient failure
+    pub idempotent: bool,
+    /// Whether the device waits for the user to approve or reject the command before answering
+    pub user_confirmation: bool,
+    /// Answers the device's requests until it completes the command
+    pub interactive: Option<Box<dyn Interactive>>,
+    /// App which must be running on the device, opened automatically before sending the command
+    pub app: Option<&'static str>,
+}
+
+impl QueuedCommand {
+    pub fn new(name: &'static str, apdu: APDUCommand<Vec<u8>>) -> Self {
+        Self::chunked(name, vec![apdu])
+    }
+
+    /// A command made of several APDUs, answered by the answer to the last one
+    pub fn chunked(name: &'static str, apdus: Vec<APDUCommand<Vec<u8>>>) -> Self {
+        Self {
+            id: CommandId::next(),
+            name,
+            priority: Priority::default(),
+            apdus,
+            timeout: None,
+            idempotent: false,
+            user_confirmation: false,
+            interactive: None,
+            app: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Opt in the retry policy of [LedgerConfig](crate::config::LedgerConfig)
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Mark the command as blocking until the user presses buttons on the device
+    pub fn with_user_confirmation(mut self) -> Self {
+        self.user_confirmation = true;
+        self
+    }
+
+    /// Keep exchanging with the device through `interactive` after the last APDU got answered
+    pub fn with_interactive(mut self, interactive: impl Interactive + 'static) -> Self {
+        self.interactive = Some(Box::new(interactive));
+        self
+    }
+
+    /// Require the app of the given name, as listed on the dashboard, to be running
+    pub fn for_app(mut self, app: &'static str) -> Self {
+        self.app = Some(app);
+        self
+    }
+}
+
+/// Result of exchanging a command with the device
+pub(crate) struct Exchanged {
+    pub result: eyre::Result<APDUAnswer<Vec<u8>>>,
+    pub attempts: u32,
+}
+
+/// The command currently being exchanged with the device
+pub(crate) struct InFlight {
+    pub id: CommandId,
+    pub name: &'static str,
+    pub timeout: Duration,
+    pub user_confirmation: bool,
+    pub task: Task<Exchanged>,
+}
+
+/// Per-device FIFO queue of commands.
+///
+/// Ledger devices can only handle one command at a time, so every command for a device goes through its queue and is sent only once the previous one got answered.
+#[derive(Component, Default)]
+pub struct CommandQueue {
+    pending: VecDeque<QueuedCommand>,
+    pub(crate) in_flight: Option<InFlight>,
+}
+
+impl CommandQueue {
+    /// Push a command behind every pending command of the same or higher priority
+    pub fn push(&mut self, command: QueuedCommand) -> CommandId {
+        let id = command.id;
+        let index = self
+            .pending
+            .iter()
+            .position(|c| c.priority < command.priority)
+            .unwrap_or(self.pending.len());
+        self.pending.insert(index, command);
+        id
+    }
+
+    /// Remove a command which hasn't been sent to the device yet
+    pub fn cancel(&mut self, id: CommandId) -> Option<QueuedCommand> {
+        let index = self.pending.iter().position(|c| c.id == id)?;
+        self.pending.remove(index)
+    }
+
+    /// Number of commands waiting or being exchanged with the device
+    pub fn depth(&self) -> usize {
+        self.pending.len() + usize::from(self.in_flight.is_some())
+    }
+
+    /// Whether a command is currently being exchanged with the device
+    pub fn is_busy(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Commands waiting to be sent, in sending order
+    pub fn pending(&self) -> impl Iterator<Item = &QueuedCommand> {
+        self.pending.iter()
+    }
+
+    /// Next command to send, unless it requires an app other than the running one
+    pub(crate) fn pop(&mut self, running_app: Option<&RunningApp>) -> Option<QueuedCommand> {
+        let ready = match self.pending.front()?.app {
+            Some(app) => running_app.is_some_and(|r| r.name == app),
+            None => true,
+        };
+
+        ready.then(|| self.pending.pop_front()).flatten()
+    }
+
+    /// Remove every pending command requiring the given app
+    pub(crate) fn drain_app(&mut self, app: &str) -> Vec<QueuedCommand> {
+        let (drained, kept): (Vec<_>, Vec<_>) =
+            self.pending.drain(..).partition(|c| c.app == Some(app));
+        self.pending = kept.into();
+        drained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::tasks::TaskPool;
+
+    fn command(name: &'static str) -> QueuedCommand {
+        QueuedCommand::new(
+            name,
+            APDUCommand {
+                cla: 0xe0,
+                ins: 0x01,
+                p1: 0x00,
+                p2: 0x00,
+                data: Vec::new(),
+            },
+        )
+    }
+
+    fn names(queue: &CommandQueue) -> Vec<&'static str> {
+        queue.pending().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn high_priority_jumps_ahead() {
+        let mut queue = CommandQueue::default();
+        queue.push(command("low").with_priority(Priority::Low));
+        queue.push(command("normal"));
+        queue.push(command("high").with_priority(Priority::High));
+
+        assert_eq!(names(&queue), ["high", "normal", "low"]);
+        assert_eq!(queue.pop(None).map(|c| c.name), Some("high"));
+    }
+
+    #[test]
+    fn same_priority_is_fifo() {
+        let mut queue = CommandQueue::default();
+        queue.push(command("first"));
+        queue.push(command("urgent").with_priority(Priority::High));
+        queue.push(command("second"));
+        queue.push(command("third"));
+
+        assert_eq!(names(&queue), ["urgent", "first", "second", "third"]);
+    }
+
+    #[test]
+    fn cancel_removes_pending_command() {
+        let mut queue = CommandQueue::default();
+        let first = queue.push(command("first"));
+        let second = queue.push(command("second"));
+
+        assert_eq!(queue.cancel(first).map(|c| c.id), Some(first));
+        assert!(queue.cancel(first).is_none());
+        assert_eq!(names(&queue), ["second"]);
+        assert_eq!(queue.pop(None).map(|c| c.id), Some(second));
+        assert!(queue.cancel(second).is_none());
+    }
+
+    #[test]
+    fn in_flight_counts_in_depth() {
+        let mut queue = CommandQueue::default();
+        queue.push(command("first"));
+        queue.push(command("second"));
+        assert_eq!(queue.depth(), 2);
+        assert!(!queue.is_busy());
+
+        let sent = queue.pop(None).unwrap();
+        queue.in_flight = Some(InFlight {
+            id: sent.id,
+            name: sent.name,
+            timeout: Duration::from_secs(1),
+            user_confirmation: false,
+            task: TaskPool::new().spawn(async {
+                Exchanged {
+                    result: APDUAnswer::from_answer(vec![0x90, 0x00]),
+                    attempts: 1,
+                }
+            }),
+        });
+        assert_eq!(queue.depth(), 2);
+        assert!(queue.is_busy());
+
+        queue.in_flight = None;
+        assert_eq!(queue.depth(), 1);
+        assert!(!queue.is_busy());
+    }
+
+    #[test]
+    fn commands_wait_for_their_app() {
+        let mut queue = CommandQueue::default();
+        queue.push(command("sign").for_app("Ethereum"));
+        queue.push(command("version"));
+        let running = |name: &str| RunningApp {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+        };
+
+        assert!(queue.pop(None).is_none());
+        assert!(queue.pop(Some(&running("Bitcoin"))).is_none());
+        assert_eq!(
+            queue.pop(Some(&running("Ethereum"))).map(|c| c.name),
+            Some("sign")
+        );
+        assert_eq!(queue.pop(None).map(|c| c.name), Some("version"));
+    }
+
+    #[test]
+    fn drain_app_keeps_other_commands() {
+        let mut queue = CommandQueue::default();
+        queue.push(command("eth address").for_app("Ethereum"));
+        queue.push(command("btc fingerprint").for_app("Bitcoin"));
+        queue.push(command("version"));
+        queue.push(command("eth signature").for_app("Ethereum"));
+
+        let drained: Vec<_> = queue.drain_app("Ethereum").iter().map(|c| c.name).collect();
+        assert_eq!(drained, ["eth address", "eth signature"]);
+        assert_eq!(names(&queue), ["btc fingerprint", "version"]);
+        assert!(queue.drain_app("Ethereum").is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::queue::CommandId;
+use bevy::{ecs::system::Resource, log};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// One APDU exchanged with a device, as a line of a session file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    /// When the APDU got sent, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Bits of the device entity, see `Entity::from_bits`
+    pub device_id: u64,
+    /// Vendor and product ids of the device
+    pub device: String,
+    /// Name of the queued command the APDU belongs to
+    pub command: String,
+    pub command_id: CommandId,
+    /// Hex encoded APDU
+    pub apdu: String,
+    /// Hex encoded answer including the status word, `None` if the exchange failed
+    pub answer: Option<String>,
+    /// Why the exchange failed
+    pub error: Option<String>,
+    /// Time between sending the APDU and receiving the answer, in microseconds
+    pub latency_us: u64,
+}
+
+/// Records every APDU exchanged with the devices to a JSON lines session file, e.g. to attach the exact device traffic to a bug report.
+///
+/// Recording is opt-in: insert the recorder as a resource to start it.
+#[derive(Resource, Clone)]
+pub struct ApduRecorder {
+    file: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl ApduRecorder {
+    /// Create the session file, truncating it if it already exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(LineWriter::new(file))),
+        })
+    }
+
+    /// Append an exchange to the session file. Failing to record doesn't fail the exchange, it only gets logged.
+    pub fn record(&self, exchange: &RecordedExchange) {
+        let mut file = self.file.lock().unwrap();
+        let result = serde_json::to_writer(&mut *file, exchange)
+            .map_err(io::Error::from)
+            .and_then(|_| file.write_all(b"\n"));
+
+        if let Err(err) = result {
+            log::warn!("Cannot record APDU exchange: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn exchanges() -> Vec<RecordedExchange> {
+        [
+            r#"{"timestamp_ms":1700000000000,"device_id":4294967296,"device":"2c97:4011","command":"GetAppAndVersion","command_id":3,"apdu":"b001000000","answer":"0105424f4c4f5305312e342e309000","error":null,"latency_us":5400}"#,
+            r#"{"timestamp_ms":1700000000010,"device_id":4294967296,"device":"2c97:4011","command":"GetVersion","command_id":4,"apdu":"e001000000","answer":null,"error":"Ledger device: timed out waiting for an answer","latency_us":30000000}"#,
+        ]
+        .into_iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn records_one_exchange_per_line() {
+        let path =
+            std::env::temp_dir().join(format!("bevy_ledger_record_{}.jsonl", std::process::id()));
+        fs::write(&path, "left over from a previous session\n").unwrap();
+
+        let recorder = ApduRecorder::create(&path).unwrap();
+        let cloned = recorder.clone();
+        let [first, second] = &exchanges()[..] else {
+            unreachable!();
+        };
+        recorder.record(first);
+        cloned.record(second);
+        drop((recorder, cloned));
+
+        let session = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let recorded: Vec<RecordedExchange> = session
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(recorded, exchanges());
+    }
+}
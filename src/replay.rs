@@ -0,0 +1,271 @@
+use crate::{apdu::APDUAnswer, error::DeviceHIDError, record::RecordedExchange};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::Mutex,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("replay: cannot read session: {0}")]
+    Io(#[from] io::Error),
+    #[error("replay: line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("replay: line {0}: invalid hex")]
+    Hex(usize),
+    #[error("replay: expected command {expected}, got {got}")]
+    Mismatch { expected: String, got: String },
+    #[error("replay: no recorded answer left for command {0}")]
+    Exhausted(String),
+}
+
+/// How the commands sent to a replayed device are matched against the recorded ones
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Commands must be sent in the recorded order and match byte for byte
+    #[default]
+    Strict,
+    /// Each command is answered by the first remaining exchange with the same CLA and INS
+    Lenient,
+}
+
+/// Recorded exchange, decoded
+struct Replayed {
+    apdu: Vec<u8>,
+    answer: Result<Vec<u8>, String>,
+}
+
+/// Answers commands from a session recorded by [ApduRecorder](crate::record::ApduRecorder), to reproduce a device's behavior without the device.
+///
+/// Spawn it with [Device::replay](crate::device::Device::replay). Disable the heartbeat in [LedgerConfig](crate::config::LedgerConfig) unless it was recorded too.
+pub struct ReplaySession {
+    mode: ReplayMode,
+    /// Product id of the recorded device, if known
+    product_id: Option<u16>,
+    exchanges: Mutex<VecDeque<Replayed>>,
+}
+
+impl ReplaySession {
+    /// Load a JSON lines session file
+    pub fn load(path: impl AsRef<Path>, mode: ReplayMode) -> Result<Self, ReplayError> {
+        let reader = BufReader::new(File::open(path)?);
+        let exchanges = reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+            .map(|(i, line)| {
+                serde_json::from_str(&line?).map_err(|source| ReplayError::Parse {
+                    line: i + 1,
+                    source,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Self::new(exchanges, mode)
+    }
+
+    /// Session replaying the given exchanges, e.g. the ones of a single device from a session file
+    pub fn new(exchanges: Vec<RecordedExchange>, mode: ReplayMode) -> Result<Self, ReplayError> {
+        let product_id = exchanges.first().and_then(|e| {
+            let (_, product_id) = e.device.split_once(':')?;
+            u16::from_str_radix(product_id, 16).ok()
+        });
+
+        let exchanges = exchanges
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let apdu = hex::decode(&e.apdu).map_err(|_| ReplayError::Hex(i + 1))?;
+                let answer = match (e.answer, e.error) {
+                    (Some(answer), _) => {
+                        Ok(hex::decode(answer).map_err(|_| ReplayError::Hex(i + 1))?)
+                    }
+                    (None, error) => Err(error.unwrap_or_default()),
+                };
+                Ok(Replayed { apdu, answer })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(Self {
+            mode,
+            product_id,
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    pub fn mode(&self) -> ReplayMode {
+        self.mode
+    }
+
+    pub(crate) fn product_id(&self) -> Option<u16> {
+        self.product_id
+    }
+
+    /// Number of recorded exchanges not replayed yet, e.g. to check a test went through the whole session
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().len()
+    }
+
+    /// Answer a serialized command with the matching recorded answer
+    pub(crate) fn answer(&self, apdu: &[u8]) -> eyre::Result<APDUAnswer<Vec<u8>>> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let index = match self.mode {
+            ReplayMode::Strict => {
+                let next = exchanges
+                    .front()
+                    .ok_or_else(|| ReplayError::Exhausted(hex::encode(apdu)))?;
+                if next.apdu != apdu {
+                    return Err(ReplayError::Mismatch {
+                        expected: hex::encode(&next.apdu),
+                        got: hex::encode(apdu),
+                    }
+                    .into());
+                }
+                0
+            }
+            ReplayMode::Lenient => exchanges
+                .iter()
+                .position(|e| e.apdu.get(..2) == apdu.get(..2))
+                .ok_or_else(|| ReplayError::Exhausted(hex::encode(apdu)))?,
+        };
+
+        match exchanges.remove(index).unwrap().answer {
+            Ok(answer) => APDUAnswer::from_answer(answer),
+            Err(err) if err == DeviceHIDError::Timeout.to_string() => {
+                Err(DeviceHIDError::Timeout.into())
+            }
+            Err(err) => Err(eyre::eyre!(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exchange of `apdu`, answered by `answer` or failing with `error`
+    fn exchange(apdu: &str, answer: Option<&str>, error: Option<&str>) -> RecordedExchange {
+        serde_json::from_value(serde_json::json!({
+            "timestamp_ms": 0,
+            "device_id": 0,
+            "device": "2c97:4011",
+            "command": "Test",
+            "command_id": 0,
+            "apdu": apdu,
+            "answer": answer,
+            "error": error,
+            "latency_us": 0,
+        }))
+        .unwrap()
+    }
+
+    fn session(mode: ReplayMode) -> ReplaySession {
+        let exchanges = vec![
+            exchange("e001000000", Some("01029000"), None),
+            exchange("b001000000", Some("03049000"), None),
+        ];
+        ReplaySession::new(exchanges, mode).unwrap()
+    }
+
+    fn answer(session: &ReplaySession, apdu: &str) -> eyre::Result<Vec<u8>> {
+        session
+            .answer(&hex::decode(apdu).unwrap())
+            .map(|a| a.data().to_vec())
+    }
+
+    fn replay_error(result: eyre::Result<Vec<u8>>) -> ReplayError {
+        result.unwrap_err().downcast().unwrap()
+    }
+
+    #[test]
+    fn strict_replays_in_order() {
+        let session = session(ReplayMode::Strict);
+        assert_eq!(session.product_id(), Some(0x4011));
+
+        assert_eq!(answer(&session, "e001000000").unwrap(), [0x01, 0x02]);
+        assert_eq!(answer(&session, "b001000000").unwrap(), [0x03, 0x04]);
+        assert_eq!(session.remaining(), 0);
+    }
+
+    #[test]
+    fn strict_mismatch() {
+        let session = session(ReplayMode::Strict);
+
+        let err = replay_error(answer(&session, "b001000000"));
+        assert!(matches!(
+            err,
+            ReplayError::Mismatch { expected, got } if expected == "e001000000" && got == "b001000000"
+        ));
+        assert_eq!(session.remaining(), 2);
+
+        let err = replay_error(answer(&session, "e001010000"));
+        assert!(matches!(err, ReplayError::Mismatch { .. }));
+    }
+
+    #[test]
+    fn lenient_matches_cla_and_ins() {
+        let session = session(ReplayMode::Lenient);
+
+        // Out of order, with other parameters and data
+        assert_eq!(answer(&session, "b00102030101").unwrap(), [0x03, 0x04]);
+        assert_eq!(answer(&session, "e0010000").unwrap(), [0x01, 0x02]);
+        assert_eq!(session.remaining(), 0);
+    }
+
+    #[test]
+    fn lenient_without_match() {
+        let session = session(ReplayMode::Lenient);
+
+        let err = replay_error(answer(&session, "e002000000"));
+        assert!(matches!(err, ReplayError::Exhausted(apdu) if apdu == "e002000000"));
+        assert_eq!(session.remaining(), 2);
+    }
+
+    #[test]
+    fn exhausted() {
+        let session = ReplaySession::new(Vec::new(), ReplayMode::Strict).unwrap();
+        assert_eq!(session.product_id(), None);
+
+        let err = replay_error(answer(&session, "e001000000"));
+        assert!(matches!(err, ReplayError::Exhausted(apdu) if apdu == "e001000000"));
+    }
+
+    #[test]
+    fn timeout_round_trip() {
+        let timeout = DeviceHIDError::Timeout.to_string();
+        let exchanges = vec![
+            exchange("e001000000", None, Some(&timeout)),
+            exchange("e001000000", None, Some("Ledger device: Io error")),
+        ];
+        let session = ReplaySession::new(exchanges, ReplayMode::Strict).unwrap();
+
+        let err = answer(&session, "e001000000").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DeviceHIDError>(),
+            Some(DeviceHIDError::Timeout)
+        ));
+
+        // Other errors are replayed as their message only
+        let err = answer(&session, "e001000000").unwrap_err();
+        assert!(err.downcast_ref::<DeviceHIDError>().is_none());
+        assert_eq!(err.to_string(), "Ledger device: Io error");
+    }
+
+    #[test]
+    fn invalid_hex() {
+        let exchanges = vec![
+            exchange("e001000000", Some("9000"), None),
+            exchange("e0010000zz", Some("9000"), None),
+        ];
+        let err = ReplaySession::new(exchanges, ReplayMode::Strict)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ReplayError::Hex(2)));
+    }
+}
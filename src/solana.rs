@@ -0,0 +1,61 @@
+use crate::path::DerivationPath;
+use bevy::ecs::component::Component;
+
+/// Configuration of the Solana app, inserted on the device entity once received
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SolAppConfiguration {
+    /// Whether the user allowed signing transactions the app cannot decode
+    pub blind_signing_enabled: bool,
+    /// Whether public keys are shown in full on the device, rather than shortened
+    pub long_pubkey_display: bool,
+    pub version: (u8, u8, u8),
+}
+
+impl SolAppConfiguration {
+    /// Decode `blind signing | pubkey display | major | minor | patch`
+    pub(crate) fn from_answer(data: &[u8]) -> Option<Self> {
+        let [blind_signing, pubkey_display, major, minor, patch] = *data.get(..5)? else {
+            return None;
+        };
+
+        Some(Self {
+            blind_signing_enabled: blind_signing != 0,
+            long_pubkey_display: pubkey_display == 0,
+            version: (major, minor, patch),
+        })
+    }
+}
+
+/// The Solana app. Send it [AppCommand](crate::event::app::AppCommand)`<Solana>`s and read the [AppResponse](crate::event::app::AppResponse)`<Solana>`s, the app gets opened first if needed.
+pub struct Solana;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolCommand {
+    /// Get the version of the app and its settings, which also get inserted on the device entity
+    GetAppConfiguration,
+    /// Get the ed25519 public key of a BIP-44 path, e.g. `44'/501'/0'/0'`
+    GetPubkey {
+        /// Every component must be hardened
+        path: DerivationPath,
+        /// Display the address on the device and wait for the user to confirm it
+        display: bool,
+    },
+    /// Sign a serialized transaction message, the user has to approve the transaction
+    SignMessage {
+        /// Every component must be hardened
+        path: DerivationPath,
+        message: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolResponse {
+    AppConfiguration(SolAppConfiguration),
+    Pubkey {
+        pubkey: [u8; 32],
+        /// Base58 encoded public key
+        address: String,
+    },
+    /// ed25519 signature
+    Signature([u8; 64]),
+}
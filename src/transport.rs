@@ -1,25 +1,107 @@
 use crate::{
     apdu::{APDUAnswer, APDUCommand},
-    constant::{LEDGER_CHANNEL, LEDGER_PACKET_READ_SIZE, LEDGER_PACKET_WRITE_SIZE, LEDGER_TIMEOUT},
-    device::Device,
+    constant::{LEDGER_CHANNEL, LEDGER_PACKET_READ_SIZE, LEDGER_PACKET_WRITE_SIZE},
+    device::{Device, DeviceKind},
     error::DeviceHIDError,
+    replay::ReplaySession,
 };
 use bevy::{ecs::system::Resource, log};
 use byteorder::{BigEndian, ReadBytesExt};
-use hidapi::{HidApi, HidDevice};
-use std::{io::Cursor, ops::Deref, sync::Mutex};
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+use std::{
+    io::{Cursor, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-#[derive(Resource)]
-pub struct Transport {
-    device: Mutex<HidDevice>,
+/// Channel to exchange APDUs with a device, depending on its kind
+pub enum Transport {
+    Hid(HidTransport),
+    Replay(Arc<ReplaySession>),
+    Tcp(TcpTransport),
 }
 
 impl Transport {
     pub fn open(device: &Device) -> Result<Transport, DeviceHIDError> {
+        match device.kind() {
+            DeviceKind::Hid(info) => HidTransport::open(info).map(Self::Hid),
+            DeviceKind::Replay(session) => Ok(Self::Replay(session.clone())),
+            DeviceKind::Tcp(address) => TcpTransport::open(address).map(Self::Tcp),
+        }
+    }
+
+    pub fn exchange(
+        &self,
+        command: APDUCommand<Vec<u8>>,
+        timeout: Duration,
+    ) -> eyre::Result<APDUAnswer<Vec<u8>>> {
+        match self {
+            Self::Hid(transport) => transport.exchange(command, timeout),
+            Self::Replay(session) => session.answer(&command.serialize()),
+            Self::Tcp(transport) => transport.exchange(command, timeout),
+        }
+    }
+}
+
+/// Speculos APDU server: commands are prefixed by their length, answers by the length of their payload
+pub struct TcpTransport {
+    stream: Mutex<TcpStream>,
+}
+
+impl TcpTransport {
+    pub fn open(address: &SocketAddr) -> Result<TcpTransport, DeviceHIDError> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream: Mutex::new(stream),
+        })
+    }
+
+    pub fn exchange<I: Deref<Target = [u8]>>(
+        &self,
+        command: APDUCommand<I>,
+        timeout: Duration,
+    ) -> eyre::Result<APDUAnswer<Vec<u8>>> {
+        let mut stream = self.stream.lock().unwrap();
+        let command = command.serialize();
+        log::info!("[{:3}] << {:}", command.len(), hex::encode(&command));
+
+        stream.set_read_timeout(Some(timeout).filter(|t| !t.is_zero()))?;
+        stream.write_all(&(command.len() as u32).to_be_bytes())?;
+        stream.write_all(&command)?;
+
+        let read = |stream: &mut TcpStream, buffer: &mut [u8]| {
+            stream.read_exact(buffer).map_err(|err| match err.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => DeviceHIDError::Timeout,
+                _ => DeviceHIDError::Io(err),
+            })
+        };
+
+        let mut len = [0u8; 4];
+        read(&mut stream, &mut len)?;
+        // The status word isn't counted
+        let mut answer = vec![0u8; u32::from_be_bytes(len) as usize + 2];
+        read(&mut stream, &mut answer)?;
+        log::info!("[{:3}] >> {:}", answer.len(), hex::encode(&answer));
+
+        APDUAnswer::from_answer(answer)
+    }
+}
+
+#[derive(Resource)]
+pub struct HidTransport {
+    device: Mutex<HidDevice>,
+}
+
+impl HidTransport {
+    pub fn open(info: &DeviceInfo) -> Result<HidTransport, DeviceHIDError> {
         let api = Self::api();
-        let device = device.open(&api)?;
+        let device = info.open_device(&api)?;
         device.set_blocking_mode(true)?;
-        let transport = Transport::new(device);
+        let transport = HidTransport::new(device);
 
         Ok(transport)
     }
@@ -33,12 +115,14 @@ impl Transport {
     pub fn exchange<I: Deref<Target = [u8]>>(
         &self,
         command: APDUCommand<I>,
+        timeout: Duration,
     ) -> eyre::Result<APDUAnswer<Vec<u8>>> {
         let device = &self.device.lock().unwrap();
-        Self::write_apdu(&device, LEDGER_CHANNEL, &command.serialize())?;
+        Self::write_apdu(device, LEDGER_CHANNEL, &command.serialize())?;
 
         let mut answer: Vec<u8> = Vec::with_capacity(256);
-        Self::read_apdu(&device, LEDGER_CHANNEL, &mut answer)?;
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        Self::read_apdu(device, LEDGER_CHANNEL, timeout, &mut answer)?;
 
         let answer = APDUAnswer::from_answer(answer)
             .map_err(|_| DeviceHIDError::Comm("response was too short"))?;
@@ -94,6 +178,7 @@ impl Transport {
     fn read_apdu(
         device: &HidDevice,
         channel: u16,
+        timeout: i32,
         apdu_answer: &mut Vec<u8>,
     ) -> eyre::Result<usize> {
         let mut buffer = vec![0u8; LEDGER_PACKET_READ_SIZE as usize];
@@ -101,7 +186,13 @@ impl Transport {
         let mut expected_apdu_len = 0usize;
 
         loop {
-            let res = device.read_timeout(&mut buffer, LEDGER_TIMEOUT)?;
+            let res = device
+                .read_timeout(&mut buffer, timeout)
+                .map_err(DeviceHIDError::Hid)?;
+
+            if res == 0 {
+                return Err(DeviceHIDError::Timeout.into());
+            }
 
             if (sequence_idx == 0 && res < 7) || res < 5 {
                 return Err(DeviceHIDError::Comm("Read error. Incomplete header").into());
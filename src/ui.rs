@@ -1,16 +1,50 @@
-use crate::{device::Device, event::general::*};
-use bevy::{ecs::entity::Entity, log, prelude::*};
+mod apdu_console;
+mod app_manager;
+mod device_info;
+mod device_list;
+mod navigation;
+mod text_input;
+mod toast;
+mod widget;
+
+use crate::{
+    device::{InstalledApps, SelectedDevice},
+    event::general::*,
+};
+use apdu_console::ApduConsolePlugin;
+use app_manager::AppManagerPlugin;
+use bevy::prelude::*;
+use device_info::DeviceInfoPlugin;
+use device_list::DeviceListPlugin;
+pub use navigation::{
+    Focus, Shortcuts, DEVICE_INFO, DEVICE_NAME, LIST_APPS, REFRESH_INFO, SCAN_DEVICES,
+};
+use navigation::{NavigationPlugin, Shortcut};
+use text_input::{TextInput, TextInputPlugin, TextSubmitted};
+use toast::ToastPlugin;
+pub use toast::{Notify, ToastLevel};
+use widget::{Theme, Widgets};
 
 pub struct Ui2DPlugin;
 
 impl Plugin for Ui2DPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_buttons).add_systems((
-            button_hover_style,
-            on_click_scan_devices,
-            on_click_get_version,
-            on_click_open_app,
-        ));
+        app.init_resource::<Theme>()
+            .add_plugin(NavigationPlugin)
+            .add_plugin(DeviceListPlugin)
+            .add_plugin(DeviceInfoPlugin)
+            .add_plugin(TextInputPlugin)
+            .add_plugin(AppManagerPlugin)
+            .add_plugin(ApduConsolePlugin)
+            .add_plugin(ToastPlugin)
+            .add_startup_system(spawn_buttons)
+            .add_systems((
+                button_hover_style,
+                on_click_scan_devices,
+                on_click_get_version,
+                on_click_open_app,
+                suggest_installed_apps,
+            ));
     }
 }
 
@@ -23,144 +57,68 @@ struct GetVersionButton;
 #[derive(Component)]
 struct OpenAppButton;
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.35, 0.35);
+/// Name of the app to open
+#[derive(Component)]
+struct AppNameInput;
+
+/// Shown when a command is issued before any device got selected
+const NO_DEVICE: &str = "No device is detected by device manager. Make sure to scan devices first.";
 
 /// Spawn buttons
-fn spawn_buttons(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn spawn_buttons(mut commands: Commands, theme: Res<Theme>) {
     commands.spawn(Camera2dBundle::default());
 
-    // Scan devices button
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                size: Size::width(Val::Percent(100.0)),
-                justify_content: JustifyContent::Center,
-                padding: UiRect::vertical(Val::Px(10.0)),
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent
-                .spawn((
-                    ScanButton,
-                    ButtonBundle {
-                        style: Style {
-                            size: Size::new(Val::Px(120.0), Val::Px(40.0)),
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        background_color: NORMAL_BUTTON.into(),
-                        ..default()
-                    },
-                ))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Scan devices",
-                        TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 20.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    ));
-                });
-        });
-
-    // Get version button
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                size: Size::width(Val::Percent(100.0)),
-                justify_content: JustifyContent::Center,
-                padding: UiRect::vertical(Val::Px(10.0)),
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent
-                .spawn((
-                    ButtonBundle {
-                        style: Style {
-                            size: Size::new(Val::Px(120.0), Val::Px(40.0)),
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        background_color: NORMAL_BUTTON.into(),
-                        ..default()
-                    },
-                    GetVersionButton,
-                ))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Device info",
-                        TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 20.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    ));
-                });
-        });
+    widget::panel(&mut commands, &theme, None, |parent| {
+        let size = theme.button_size;
+        parent.button(
+            &theme,
+            "Scan devices",
+            size,
+            (ScanButton, Shortcut(SCAN_DEVICES)),
+        );
+        parent.button(
+            &theme,
+            "Device info",
+            size,
+            (GetVersionButton, Shortcut(DEVICE_INFO)),
+        );
 
-    // Open app button
-    // Todo: Text field
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                size: Size::width(Val::Percent(100.0)),
-                justify_content: JustifyContent::Center,
-                padding: UiRect::vertical(Val::Px(10.0)),
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent
-                .spawn((
-                    ButtonBundle {
-                        style: Style {
-                            size: Size::new(Val::Px(200.0), Val::Px(40.0)),
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        background_color: NORMAL_BUTTON.into(),
-                        ..default()
-                    },
-                    OpenAppButton,
-                ))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Open Ethereum app",
-                        TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 20.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    ));
-                });
+        // App name field and open app button
+        parent.row(|parent| {
+            parent.text_input(
+                &theme,
+                TextInput::new("App name, up and down to cycle"),
+                200.0,
+                AppNameInput,
+            );
+            parent.button(&theme, "Open app", theme.button_size, OpenAppButton);
         });
+    });
 }
 
-/// Change button color on mouse hover
+/// Change button color on mouse hover, and highlight the focused one
 fn button_hover_style(
-    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<Button>)>,
+    theme: Res<Theme>,
+    focus: Res<Focus>,
+    mut query: Query<(Entity, Ref<Interaction>, &mut BackgroundColor), With<Button>>,
 ) {
-    query.for_each_mut(|(interaction, mut color)| {
+    query.for_each_mut(|(entity, interaction, mut color)| {
+        if !interaction.is_changed() && !focus.is_changed() {
+            return;
+        }
+
         match *interaction {
             Interaction::Clicked => {
-                *color = PRESSED_BUTTON.into();
+                *color = theme.pressed_button_color.into();
             }
             Interaction::Hovered => {
-                *color = HOVERED_BUTTON.into();
+                *color = theme.hovered_button_color.into();
+            }
+            Interaction::None if focus.0 == Some(entity) => {
+                *color = theme.focused_button_color.into();
             }
             Interaction::None => {
-                *color = NORMAL_BUTTON.into();
+                *color = theme.button_color.into();
             }
         };
     });
@@ -168,60 +126,97 @@ fn button_hover_style(
 
 /// Emit `ScanDevices` event when user clicks button
 fn on_click_scan_devices(
-    query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<ScanButton>)>,
+    query: Query<&Interaction, (Changed<Interaction>, With<ScanButton>)>,
     mut scan_devices: EventWriter<ScanDevices>,
 ) {
     query.for_each(|interaction| {
-        match *interaction {
-            Interaction::Clicked => {
-                scan_devices.send(ScanDevices);
-            }
-            _ => {}
-        };
+        if *interaction == Interaction::Clicked {
+            scan_devices.send(ScanDevices);
+        }
     });
 }
 
 /// Emit `GetDeviceInfo` event on click
 fn on_click_get_version(
-    interactions: Query<&Interaction, (Changed<Interaction>, With<Button>, With<GetVersionButton>)>,
-    devices: Query<(Entity, &Device)>,
+    interactions: Query<&Interaction, (Changed<Interaction>, With<GetVersionButton>)>,
+    selected: Res<SelectedDevice>,
     mut get_version: EventWriter<GetVersion>,
+    mut notify: EventWriter<Notify>,
 ) {
     interactions.for_each(|interaction| {
-        match *interaction {
-            Interaction::Clicked => {
-                if devices.is_empty() {
-                    log::error!(
-                        "No device is detected by device manager. Make sure to scan devices first."
-                    );
-                } else {
-                    get_version.send(GetVersion {
-                        // Todo: Let user choose a device
-                        device_id: devices.single().0,
-                    });
-                }
+        if *interaction == Interaction::Clicked {
+            match selected.0 {
+                Some(device_id) => get_version.send(GetVersion { device_id }),
+                None => notify.send(Notify::error(NO_DEVICE)),
             }
-            _ => {}
-        };
+        }
     });
 }
 
-/// Emit `OpenDeviceApp` event on click
+/// Emit `OpenApp` event for the typed app on click or enter
 fn on_click_open_app(
-    query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<OpenAppButton>)>,
-    devices: Query<(Entity, &Device)>,
+    query: Query<&Interaction, (Changed<Interaction>, With<OpenAppButton>)>,
+    mut submitted: EventReader<TextSubmitted>,
+    input: Query<(Entity, &TextInput), With<AppNameInput>>,
+    selected: Res<SelectedDevice>,
     mut open_app: EventWriter<OpenApp>,
+    mut notify: EventWriter<Notify>,
 ) {
-    query.for_each(|interaction| {
-        match *interaction {
-            Interaction::Clicked => {
-                open_app.send(OpenApp {
-                    // Todo: Let user choose a device
-                    device_id: devices.single().0,
-                    name: "Ethereum",
-                });
-            }
-            _ => {}
-        };
-    });
+    let Ok((input_id, input)) = input.get_single() else {
+        return;
+    };
+
+    let clicked = query.iter().any(|i| *i == Interaction::Clicked);
+    if !clicked && !submitted.iter().any(|e| e.input == input_id) {
+        return;
+    }
+
+    let name = input.value.trim();
+    match selected.0 {
+        _ if name.is_empty() => {
+            notify.send(Notify::error("Type the name of the app to open first."))
+        }
+        Some(device_id) => open_app.send(OpenApp {
+            device_id,
+            name: name.to_string(),
+        }),
+        None => notify.send(Notify::error(NO_DEVICE)),
+    }
+}
+
+/// Fill the app name with the first app installed on the selected device once known, and cycle through them with up and down
+fn suggest_installed_apps(
+    selected: Res<SelectedDevice>,
+    keys: Res<Input<KeyCode>>,
+    devices: Query<Ref<InstalledApps>>,
+    mut input: Query<&mut TextInput, With<AppNameInput>>,
+) {
+    let Ok(mut input) = input.get_single_mut() else {
+        return;
+    };
+    let Some(apps) = selected.0.and_then(|id| devices.get(id).ok()) else {
+        return;
+    };
+    let Some(first) = apps.0.first() else {
+        return;
+    };
+
+    let step = match (
+        keys.just_pressed(KeyCode::Down),
+        keys.just_pressed(KeyCode::Up),
+    ) {
+        (true, false) => Some(1),
+        (false, true) => Some(apps.0.len() - 1),
+        _ => None,
+    };
+    if let Some(step) = step.filter(|_| input.focused) {
+        let next = apps
+            .0
+            .iter()
+            .position(|app| app.name == input.value)
+            .map_or(first, |i| &apps.0[(i + step) % apps.0.len()]);
+        input.value = next.name.clone();
+    } else if (apps.is_changed() || selected.is_changed()) && input.value.is_empty() {
+        input.value = first.name.clone();
+    }
 }
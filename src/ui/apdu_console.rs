@@ -0,0 +1,201 @@
+use super::{
+    text_input::{TextInput, TextSubmitted},
+    widget::{self, Theme, Widgets},
+};
+use crate::{
+    apdu::APDUCommand,
+    device::SelectedDevice,
+    error::APDUErrorCode,
+    event::general::{RawApduAnswered, SendRawApdu},
+};
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+
+/// History lines kept in the console
+const MAX_HISTORY: usize = 200;
+
+/// Height of the visible part of the history
+const HISTORY_HEIGHT: f32 = 200.0;
+
+/// Scrolled pixels per line of a mouse wheel
+const LINE_HEIGHT: f32 = 20.0;
+
+pub struct ApduConsolePlugin;
+
+impl Plugin for ApduConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_apdu_console).add_systems((
+            send_console_apdu,
+            show_raw_answers,
+            scroll_history,
+        ));
+    }
+}
+
+/// Field to type the APDU in
+#[derive(Component)]
+struct ApduInput;
+
+#[derive(Component)]
+struct SendApduButton;
+
+/// Sent commands and their answers, newest first, scrolled by moving it within its clipping parent
+#[derive(Component)]
+struct ApduHistory {
+    scrolled: f32,
+}
+
+/// Spawn the console panel: an input with its send button, above the history
+fn spawn_apdu_console(mut commands: Commands, theme: Res<Theme>) {
+    widget::panel(&mut commands, &theme, Some("APDU console"), |parent| {
+        parent.row(|parent| {
+            parent.text_input(
+                &theme,
+                TextInput::new("cla ins p1 p2 [length data], in hex")
+                    .accepting(|c| c.is_ascii_hexdigit() || c == ' '),
+                360.0,
+                ApduInput,
+            );
+            parent.button(&theme, "Send", theme.compact_button_size, SendApduButton);
+        });
+
+        // History, clipped to its visible part
+        let size = Size::new(Val::Px(445.0), Val::Px(HISTORY_HEIGHT));
+        parent.list(&theme, size, (), |parent| {
+            parent.spawn((ApduHistory { scrolled: 0.0 }, TextBundle::from_sections([])));
+        });
+    });
+}
+
+/// Send the typed APDU to the selected device, on click or enter
+fn send_console_apdu(
+    clicked: Query<&Interaction, (Changed<Interaction>, With<SendApduButton>)>,
+    mut submitted: EventReader<TextSubmitted>,
+    mut input: Query<(Entity, &mut TextInput), With<ApduInput>>,
+    mut history: Query<&mut Text, With<ApduHistory>>,
+    theme: Res<Theme>,
+    selected: Res<SelectedDevice>,
+    mut send: EventWriter<SendRawApdu>,
+) {
+    let Ok((input_id, mut input)) = input.get_single_mut() else {
+        return;
+    };
+
+    let clicked = clicked.iter().any(|i| *i == Interaction::Clicked);
+    let submitted = submitted.iter().any(|e| e.input == input_id);
+    if !clicked && !submitted {
+        return;
+    }
+
+    let Ok(mut text) = history.get_single_mut() else {
+        return;
+    };
+
+    let typed = input.value.replace(' ', "");
+    let Some(device_id) = selected.0 else {
+        push_line(
+            &theme,
+            &mut text,
+            "! no device selected".to_string(),
+            ERROR_COLOR,
+        );
+        return;
+    };
+    let Some(apdu) = hex::decode(&typed)
+        .ok()
+        .and_then(|bytes| APDUCommand::from_bytes(&bytes))
+    else {
+        push_line(
+            &theme,
+            &mut text,
+            format!("! invalid APDU: {typed}"),
+            ERROR_COLOR,
+        );
+        return;
+    };
+
+    push_line(&theme, &mut text, format!("> {typed}"), theme.text_color);
+    send.send(SendRawApdu { device_id, apdu });
+    input.value.clear();
+}
+
+/// Add the answers to the history, with their status word decoded
+fn show_raw_answers(
+    mut events: EventReader<RawApduAnswered>,
+    mut history: Query<&mut Text, With<ApduHistory>>,
+    theme: Res<Theme>,
+) {
+    let Ok(mut text) = history.get_single_mut() else {
+        return;
+    };
+
+    events.iter().for_each(|e| {
+        let (line, color) = match &e.result {
+            Ok(answer) => {
+                let status = match APDUErrorCode::try_from(answer.retcode()) {
+                    Ok(code) => format!("{code:?}"),
+                    Err(_) => "unknown status".to_string(),
+                };
+                let color = match answer.error_code() {
+                    Ok(APDUErrorCode::NoError) => ANSWER_COLOR,
+                    _ => ERROR_COLOR,
+                };
+                let line = format!(
+                    "< {} {:04x} ({status})",
+                    hex::encode(answer.data()),
+                    answer.retcode()
+                );
+                (line, color)
+            }
+            Err(err) => (format!("! {err}"), ERROR_COLOR),
+        };
+
+        push_line(&theme, &mut text, line, color);
+    });
+}
+
+const ANSWER_COLOR: Color = Color::rgb(0.5, 0.9, 0.5);
+const ERROR_COLOR: Color = Color::rgb(0.9, 0.4, 0.4);
+
+fn push_line(theme: &Theme, text: &mut Text, mut line: String, color: Color) {
+    if !text.sections.is_empty() {
+        line.push('\n');
+    }
+
+    let style = TextStyle {
+        color,
+        ..theme.text_style()
+    };
+    text.sections.insert(0, TextSection::new(line, style));
+    text.sections.truncate(MAX_HISTORY);
+}
+
+/// Scroll the history with the mouse wheel
+fn scroll_history(
+    mut wheel: EventReader<MouseWheel>,
+    mut history: Query<(&mut ApduHistory, &mut Style, &Node, &Parent)>,
+    clips: Query<&Node>,
+    theme: Res<Theme>,
+) {
+    let scrolled: f32 = wheel
+        .iter()
+        .map(|e| match e.unit {
+            MouseScrollUnit::Line => e.y * LINE_HEIGHT,
+            MouseScrollUnit::Pixel => e.y,
+        })
+        .sum();
+    if scrolled == 0.0 {
+        return;
+    }
+
+    history.for_each_mut(|(mut history, mut style, node, parent)| {
+        let visible = clips
+            .get(parent.get())
+            .map_or(HISTORY_HEIGHT, |n| n.size().y - 2.0 * theme.spacing);
+        let max_scroll = (node.size().y - visible).max(0.0);
+        history.scrolled = (history.scrolled + scrolled).clamp(-max_scroll, 0.0);
+        style.position.top = Val::Px(history.scrolled);
+    });
+}
@@ -0,0 +1,184 @@
+use super::{
+    navigation::{Shortcut, LIST_APPS},
+    widget::{self, Theme, Widgets},
+    Notify, NO_DEVICE,
+};
+use crate::{
+    device::{Device, InstalledApp, InstalledApps, RunningApp, SelectedDevice},
+    event::general::{ListApps, OpenApp},
+};
+use bevy::prelude::*;
+
+pub struct AppManagerPlugin;
+
+impl Plugin for AppManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_app_manager).add_systems((
+            sync_app_rows,
+            on_click_list_apps,
+            on_click_open_installed_app,
+        ));
+    }
+}
+
+/// Rows of the apps installed on the selected device
+#[derive(Component)]
+struct AppList;
+
+#[derive(Component)]
+struct ListAppsButton;
+
+/// Open the app of the row, only shown on the dashboard as the device cannot open an app from another one
+#[derive(Component)]
+struct OpenInstalledAppButton(String);
+
+/// Spawn the manager screen: a button to list the apps, above the list itself
+fn spawn_app_manager(mut commands: Commands, theme: Res<Theme>) {
+    widget::panel(&mut commands, &theme, Some("Installed apps"), |parent| {
+        parent.button(
+            &theme,
+            "List apps",
+            theme.button_size,
+            (ListAppsButton, Shortcut(LIST_APPS)),
+        );
+        parent.list(&theme, Size::width(Val::Px(445.0)), AppList, |_| {});
+    });
+}
+
+/// Rows shown for an app: its name and summary
+type AppRow = (String, String);
+
+/// Rebuild the rows when the selected device, its apps or its running app change. The running app gets
+/// re-inserted by every heartbeat, so the rows are compared rather than relying on change detection.
+///
+/// The rows don't offer to uninstall apps: uninstalling goes through a secure channel with Ledger's HSMs,
+/// which the device only opens for the manager API.
+fn sync_app_rows(
+    selected: Res<SelectedDevice>,
+    theme: Res<Theme>,
+    devices: Query<(&Device, Option<&InstalledApps>, Option<&RunningApp>)>,
+    list: Query<(Entity, Ref<AppList>)>,
+    mut commands: Commands,
+    mut shown: Local<Option<(Vec<AppRow>, bool)>>,
+) {
+    let Ok((list_id, list)) = list.get_single() else {
+        return;
+    };
+
+    let rows: Option<(Vec<AppRow>, bool)> = selected
+        .0
+        .and_then(|id| devices.get(id).ok())
+        .and_then(|(device, apps, running)| {
+            let rows = apps?.0.iter().map(|app| {
+                let running = running.filter(|r| r.name == app.name);
+                (app.name.clone(), describe_app(app, running, device))
+            });
+            Some((
+                rows.collect(),
+                running.is_some_and(RunningApp::is_dashboard),
+            ))
+        });
+    if *shown == rows && !list.is_added() {
+        return;
+    }
+    *shown = rows.clone();
+
+    commands.entity(list_id).despawn_descendants();
+    commands.entity(list_id).with_children(|parent| {
+        let Some((rows, on_dashboard)) = rows else {
+            parent.spawn(TextBundle::from_section(
+                "List the apps of a device to manage them",
+                theme.muted_text_style(),
+            ));
+            return;
+        };
+
+        if !on_dashboard {
+            parent.spawn(TextBundle::from_section(
+                "Quit the running app on the device to open another one",
+                theme.muted_text_style(),
+            ));
+        }
+
+        rows.into_iter().for_each(|(name, summary)| {
+            parent.row(|parent| {
+                parent.spawn(
+                    TextBundle::from_section(summary, theme.text_style()).with_style(Style {
+                        size: Size::width(Val::Px(335.0)),
+                        ..default()
+                    }),
+                );
+
+                if on_dashboard {
+                    let size = theme.compact_button_size;
+                    parent.button(&theme, "Open", size, OpenInstalledAppButton(name));
+                }
+            });
+        });
+    });
+}
+
+/// `name version - size - flags`, the version being only known for the running app
+fn describe_app(app: &InstalledApp, running: Option<&RunningApp>, device: &Device) -> String {
+    let marker = if running.is_some() { "> " } else { "" };
+    let version = running.map_or("-", |r| r.version.as_str());
+    let size = match device.model().block_size() {
+        Some(block_size) => {
+            let bytes = u32::from(app.blocks) * block_size;
+            format!("{:.1} KB", bytes as f32 / 1024.0)
+        }
+        None => format!("{} blocks", app.blocks),
+    };
+
+    format!(
+        "{marker}{} {version} - {size} - {:#06x}",
+        app.name, app.flags
+    )
+}
+
+/// Emit `ListApps` for the selected device on click
+fn on_click_list_apps(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<ListAppsButton>)>,
+    selected: Res<SelectedDevice>,
+    mut list_apps: EventWriter<ListApps>,
+    mut notify: EventWriter<Notify>,
+) {
+    if !interactions.iter().any(|i| *i == Interaction::Clicked) {
+        return;
+    }
+
+    match selected.0 {
+        Some(device_id) => list_apps.send(ListApps { device_id }),
+        None => notify.send(Notify::error(NO_DEVICE)),
+    }
+}
+
+/// Emit `OpenApp` for the app of the clicked row, if the device is still on its dashboard
+fn on_click_open_installed_app(
+    interactions: Query<(&Interaction, &OpenInstalledAppButton), Changed<Interaction>>,
+    selected: Res<SelectedDevice>,
+    running: Query<&RunningApp>,
+    mut open_app: EventWriter<OpenApp>,
+    mut notify: EventWriter<Notify>,
+) {
+    interactions
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Clicked)
+        .for_each(|(_, button)| {
+            let Some(device_id) = selected.0 else {
+                notify.send(Notify::error(NO_DEVICE));
+                return;
+            };
+
+            match running.get(device_id) {
+                Ok(running) if running.is_dashboard() => open_app.send(OpenApp {
+                    device_id,
+                    name: button.0.clone(),
+                }),
+                _ => notify.send(Notify::warning(format!(
+                    "Cannot open {}: quit the running app first",
+                    button.0
+                ))),
+            }
+        });
+}
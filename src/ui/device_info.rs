@@ -0,0 +1,206 @@
+use super::{
+    navigation::{Shortcut, DEVICE_NAME, REFRESH_INFO},
+    widget::{self, Theme, Widgets},
+    Notify, NO_DEVICE,
+};
+use crate::{
+    device::{
+        BatteryState, Device, DeviceName, DeviceVersion, LockState, RunningApp, SelectedDevice,
+    },
+    event::general::{GetAppAndVersion, GetBatteryState, GetDeviceName, GetVersion},
+};
+use bevy::prelude::*;
+
+pub struct DeviceInfoPlugin;
+
+impl Plugin for DeviceInfoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_device_info).add_systems((
+            update_device_info,
+            on_click_refresh_info,
+            on_click_get_device_name,
+        ));
+    }
+}
+
+/// Row of the panel, showing one piece of information on the selected device
+#[derive(Component, Copy, Clone)]
+enum InfoField {
+    Model,
+    Lock,
+    Firmware,
+    Mcu,
+    App,
+    Battery,
+    Name,
+}
+
+impl InfoField {
+    const ALL: [Self; 7] = [
+        Self::Model,
+        Self::Lock,
+        Self::Firmware,
+        Self::Mcu,
+        Self::App,
+        Self::Battery,
+        Self::Name,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Model => "Model",
+            Self::Lock => "Lock state",
+            Self::Firmware => "Firmware",
+            Self::Mcu => "MCU",
+            Self::App => "Running app",
+            Self::Battery => "Battery",
+            Self::Name => "Name",
+        }
+    }
+}
+
+/// Ask the selected device for its versions, running app and battery
+#[derive(Component)]
+struct RefreshInfoButton;
+
+/// Ask the selected device for its name, which the user has to allow
+#[derive(Component)]
+struct GetDeviceNameButton;
+
+/// Shown for the information the device hasn't reported yet
+const UNKNOWN: &str = "-";
+
+/// Spawn the panel describing the selected device, with buttons to query it
+fn spawn_device_info(mut commands: Commands, theme: Res<Theme>) {
+    widget::panel(
+        &mut commands,
+        &theme,
+        Some("Device information"),
+        |parent| {
+            parent.list(&theme, Size::width(Val::Px(360.0)), (), |parent| {
+                InfoField::ALL.into_iter().for_each(|field| {
+                    parent.labelled_row(&theme, field.label(), field);
+                });
+            });
+
+            parent.row(|parent| {
+                parent.button(
+                    &theme,
+                    "Refresh",
+                    theme.button_size,
+                    (RefreshInfoButton, Shortcut(REFRESH_INFO)),
+                );
+                parent.button(
+                    &theme,
+                    "Device name",
+                    theme.button_size,
+                    (GetDeviceNameButton, Shortcut(DEVICE_NAME)),
+                );
+            });
+        },
+    );
+}
+
+/// Components describing a device, each one being inserted once the device reported it
+type DeviceInfo<'a> = (
+    &'a Device,
+    Ref<'a, LockState>,
+    Option<Ref<'a, DeviceVersion>>,
+    Option<Ref<'a, RunningApp>>,
+    Option<Ref<'a, BatteryState>>,
+    Option<Ref<'a, DeviceName>>,
+);
+
+/// Render the information on the selected device when it or its components change
+fn update_device_info(
+    selected: Res<SelectedDevice>,
+    devices: Query<DeviceInfo>,
+    mut fields: Query<(&InfoField, &mut Text)>,
+    mut shown: Local<Option<Entity>>,
+) {
+    // The selected device may have been removed, clearing the panel
+    let device_id = selected.0.filter(|id| devices.contains(*id));
+    let device = device_id.and_then(|id| devices.get(id).ok());
+    let changed = device
+        .as_ref()
+        .is_some_and(|(_, lock, version, app, battery, name)| {
+            lock.is_changed()
+                || version.as_ref().is_some_and(|c| c.is_changed())
+                || app.as_ref().is_some_and(|c| c.is_changed())
+                || battery.as_ref().is_some_and(|c| c.is_changed())
+                || name.as_ref().is_some_and(|c| c.is_changed())
+        });
+    if !changed && *shown == device_id {
+        return;
+    }
+    *shown = device_id;
+
+    fields.for_each_mut(|(field, mut text)| {
+        let value = match &device {
+            None => None,
+            Some((device, lock, version, app, battery, name)) => match field {
+                InfoField::Model => Some(device.model().to_string()),
+                InfoField::Lock => Some(format!("{:?}", **lock)),
+                InfoField::Firmware => version.as_ref().map(|v| v.se_version.clone()),
+                InfoField::Mcu => version.as_ref().map(|v| v.mcu_version.clone()),
+                InfoField::App => app.as_ref().map(|a| format!("{} {}", a.name, a.version)),
+                InfoField::Battery => match device.model().has_battery() {
+                    true => battery.as_ref().map(|b| format!("{}%", b.percentage)),
+                    false => Some("none".to_string()),
+                },
+                InfoField::Name => name.as_ref().map(|n| n.0.clone()),
+            },
+        };
+        let value = value.unwrap_or_else(|| UNKNOWN.to_string());
+
+        if text.sections[1].value != value {
+            text.sections[1].value = value;
+        }
+    });
+}
+
+/// Query the versions, running app and battery of the selected device on click
+fn on_click_refresh_info(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<RefreshInfoButton>)>,
+    selected: Res<SelectedDevice>,
+    devices: Query<&Device>,
+    mut get_version: EventWriter<GetVersion>,
+    mut get_app: EventWriter<GetAppAndVersion>,
+    mut get_battery: EventWriter<GetBatteryState>,
+    mut notify: EventWriter<Notify>,
+) {
+    if !interactions.iter().any(|i| *i == Interaction::Clicked) {
+        return;
+    }
+
+    let Some((device_id, device)) = selected
+        .0
+        .and_then(|id| devices.get(id).ok().map(|d| (id, d)))
+    else {
+        notify.send(Notify::error(NO_DEVICE));
+        return;
+    };
+
+    get_version.send(GetVersion { device_id });
+    get_app.send(GetAppAndVersion { device_id });
+    if device.model().has_battery() {
+        get_battery.send(GetBatteryState { device_id });
+    }
+}
+
+/// Ask the selected device for its name on click
+fn on_click_get_device_name(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<GetDeviceNameButton>)>,
+    selected: Res<SelectedDevice>,
+    mut get_name: EventWriter<GetDeviceName>,
+    mut notify: EventWriter<Notify>,
+) {
+    if !interactions.iter().any(|i| *i == Interaction::Clicked) {
+        return;
+    }
+
+    match selected.0 {
+        Some(device_id) => get_name.send(GetDeviceName { device_id }),
+        None => notify.send(Notify::error(NO_DEVICE)),
+    }
+}
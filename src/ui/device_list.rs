@@ -0,0 +1,122 @@
+use crate::{
+    device::{Device, LockState, SelectedDevice},
+    queue::CommandQueue,
+};
+use bevy::{ecs::entity::Entity, prelude::*};
+
+use super::widget::{self, Theme, Widgets};
+
+pub struct DeviceListPlugin;
+
+impl Plugin for DeviceListPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_device_list).add_systems((
+            sync_device_entries,
+            update_device_labels.after(sync_device_entries),
+            on_click_device_entry,
+        ));
+    }
+}
+
+/// Container of the device entries
+#[derive(Component)]
+struct DeviceList;
+
+/// Button selecting the device, its text describing the device
+#[derive(Component)]
+struct DeviceEntry(Entity);
+
+/// Spawn the panel listing connected devices
+fn spawn_device_list(mut commands: Commands, theme: Res<Theme>) {
+    widget::panel(&mut commands, &theme, Some("Devices"), |parent| {
+        parent.spawn((
+            DeviceList,
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    });
+}
+
+/// Spawn an entry per connected device and despawn the ones of removed devices
+fn sync_device_entries(
+    mut commands: Commands,
+    theme: Res<Theme>,
+    list: Query<Entity, With<DeviceList>>,
+    entries: Query<(Entity, &DeviceEntry)>,
+    devices: Query<Entity, With<Device>>,
+) {
+    let Ok(list) = list.get_single() else {
+        return;
+    };
+
+    entries.for_each(|(entry_id, entry)| {
+        if !devices.contains(entry.0) {
+            commands.entity(entry_id).despawn_recursive();
+        }
+    });
+
+    devices
+        .iter()
+        .filter(|device_id| !entries.iter().any(|(_, entry)| entry.0 == *device_id))
+        .for_each(|device_id| {
+            commands.entity(list).with_children(|parent| {
+                parent.button(
+                    &theme,
+                    "",
+                    Size::new(Val::Px(360.0), theme.button_size.height),
+                    DeviceEntry(device_id),
+                );
+            });
+        });
+}
+
+/// Show the model, lock state and queue depth of each device
+fn update_device_labels(
+    selected: Res<SelectedDevice>,
+    entries: Query<(&DeviceEntry, &Children)>,
+    mut texts: Query<&mut Text>,
+    devices: Query<(&Device, &LockState, &CommandQueue)>,
+) {
+    entries.for_each(|(entry, children)| {
+        let Ok((device, lock_state, queue)) = devices.get(entry.0) else {
+            return;
+        };
+        let Some(mut text) = children.first().and_then(|c| texts.get_mut(*c).ok()) else {
+            return;
+        };
+
+        let marker = if selected.0 == Some(entry.0) {
+            "> "
+        } else {
+            ""
+        };
+        let value = format!(
+            "{marker}{} {device} - {lock_state:?} - {} queued",
+            device.model(),
+            queue.depth()
+        );
+
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    });
+}
+
+type DeviceEntryInteraction<'a> = (&'a Interaction, &'a DeviceEntry);
+
+/// Select the device on click
+fn on_click_device_entry(
+    query: Query<DeviceEntryInteraction, (Changed<Interaction>, With<Button>)>,
+    mut selected: ResMut<SelectedDevice>,
+) {
+    query.for_each(|(interaction, entry)| {
+        if *interaction == Interaction::Clicked {
+            selected.0 = Some(entry.0);
+        }
+    });
+}
@@ -0,0 +1,170 @@
+use super::text_input::TextInput;
+use bevy::{prelude::*, ui::UiSystem, utils::HashMap};
+
+// Actions of the buttons which can be triggered by a shortcut
+pub const SCAN_DEVICES: &str = "scan_devices";
+pub const DEVICE_INFO: &str = "device_info";
+pub const REFRESH_INFO: &str = "refresh_info";
+pub const DEVICE_NAME: &str = "device_name";
+pub const LIST_APPS: &str = "list_apps";
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        // Before the click handlers, which see keyboard activations as clicks within the same frame
+        app.init_resource::<Focus>()
+            .init_resource::<Shortcuts>()
+            .add_systems(
+                (
+                    focus_clicked,
+                    traverse_focus,
+                    activate_buttons,
+                    sync_text_input_focus,
+                )
+                    .chain()
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(UiSystem::Focus),
+            );
+    }
+}
+
+/// Button or text input receiving the keyboard, moved with tab and shift-tab
+#[derive(Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Focus(pub Option<Entity>);
+
+/// Key activating the buttons tagged with each action. Insert it before [Ui2DPlugin](super::Ui2DPlugin) to change them.
+#[derive(Resource, Clone, Debug)]
+pub struct Shortcuts(pub HashMap<&'static str, KeyCode>);
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self(HashMap::from_iter([
+            (SCAN_DEVICES, KeyCode::S),
+            (DEVICE_INFO, KeyCode::I),
+            (REFRESH_INFO, KeyCode::R),
+            (DEVICE_NAME, KeyCode::N),
+            (LIST_APPS, KeyCode::L),
+        ]))
+    }
+}
+
+/// Action of a button, activating it when its key of [Shortcuts] gets pressed outside of text inputs
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Shortcut(pub &'static str);
+
+/// Focus the clicked button or text input
+fn focus_clicked(
+    clicked: Query<(Entity, &Interaction), Changed<Interaction>>,
+    mut focus: ResMut<Focus>,
+) {
+    if let Some((entity, _)) = clicked
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Clicked)
+    {
+        focus.set_if_neq(Focus(Some(entity)));
+    }
+}
+
+/// Move the focus in layout order with tab, backwards with shift-tab, and drop it with escape
+fn traverse_focus(
+    keys: Res<Input<KeyCode>>,
+    mut focus: ResMut<Focus>,
+    roots: Query<Entity, (With<Node>, Without<Parent>)>,
+    children: Query<&Children>,
+    focusable: Query<(), With<Interaction>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        focus.set_if_neq(Focus(None));
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Tab) {
+        return;
+    }
+
+    // Roots are visited by entity, i.e. in spawn order, rather than in query order which depends on their components.
+    // Their descendants are visited in hierarchy order.
+    let mut order = Vec::new();
+    let mut stack: Vec<Entity> = roots.iter().collect();
+    stack.sort_unstable_by(|a, b| b.cmp(a));
+    while let Some(entity) = stack.pop() {
+        if focusable.contains(entity) {
+            order.push(entity);
+        }
+        if let Ok(children) = children.get(entity) {
+            stack.extend(children.iter().rev());
+        }
+    }
+    if order.is_empty() {
+        return;
+    }
+
+    let backwards = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let current = focus.0.and_then(|f| order.iter().position(|e| *e == f));
+    let next = match (current, backwards) {
+        (None, false) => 0,
+        (None, true) => order.len() - 1,
+        (Some(i), false) => (i + 1) % order.len(),
+        (Some(i), true) => (i + order.len() - 1) % order.len(),
+    };
+    focus.0 = Some(order[next]);
+}
+
+/// Click the focused button with enter or space, and the buttons whose shortcut got pressed. Buttons get released on the next frame.
+fn activate_buttons(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<Focus>,
+    shortcuts: Res<Shortcuts>,
+    buttons: Query<(Entity, &Shortcut)>,
+    inputs: Query<(), With<TextInput>>,
+    mut interactions: Query<&mut Interaction>,
+    mut pressed: Local<Vec<Entity>>,
+) {
+    pressed.drain(..).for_each(|entity| {
+        if let Ok(mut interaction) = interactions.get_mut(entity) {
+            if *interaction == Interaction::Clicked {
+                *interaction = Interaction::None;
+            }
+        }
+    });
+
+    // Text inputs take enter, space and letters for themselves
+    if focus.0.is_some_and(|f| inputs.contains(f)) {
+        return;
+    }
+
+    let mut activated: Vec<Entity> = buttons
+        .iter()
+        .filter(|(_, shortcut)| {
+            shortcuts
+                .0
+                .get(shortcut.0)
+                .is_some_and(|key| keys.just_pressed(*key))
+        })
+        .map(|(entity, _)| entity)
+        .collect();
+    if keys.any_just_pressed([KeyCode::Return, KeyCode::Space]) {
+        activated.extend(focus.0);
+    }
+
+    activated.into_iter().for_each(|entity| {
+        if let Ok(mut interaction) = interactions.get_mut(entity) {
+            *interaction = Interaction::Clicked;
+            pressed.push(entity);
+        }
+    });
+}
+
+/// Only the focused text input receives the typed characters
+fn sync_text_input_focus(focus: Res<Focus>, mut inputs: Query<(Entity, &mut TextInput)>) {
+    if !focus.is_changed() {
+        return;
+    }
+
+    inputs.for_each_mut(|(entity, mut input)| {
+        let focused = focus.0 == Some(entity);
+        if input.focused != focused {
+            input.focused = focused;
+        }
+    });
+}
@@ -0,0 +1,96 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+
+pub struct TextInputPlugin;
+
+impl Plugin for TextInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TextSubmitted>().add_systems((
+            type_into_text_inputs,
+            render_text_inputs.after(type_into_text_inputs),
+        ));
+    }
+}
+
+/// Single line text field, spawned on a button whose first child is the text displaying it
+#[derive(Component)]
+pub struct TextInput {
+    pub value: String,
+    /// Shown while the field is empty
+    pub placeholder: &'static str,
+    /// Characters which can be typed in, any by default
+    pub accepts: fn(char) -> bool,
+    /// Follows the keyboard [Focus](super::navigation::Focus)
+    pub focused: bool,
+}
+
+impl TextInput {
+    pub fn new(placeholder: &'static str) -> Self {
+        Self {
+            value: String::new(),
+            placeholder,
+            accepts: |_| true,
+            focused: false,
+        }
+    }
+
+    pub fn accepting(mut self, accepts: fn(char) -> bool) -> Self {
+        self.accepts = accepts;
+        self
+    }
+}
+
+/// Emitted when the user presses enter in a focused [TextInput]
+pub struct TextSubmitted {
+    pub input: Entity,
+}
+
+fn type_into_text_inputs(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut inputs: Query<(Entity, &mut TextInput)>,
+    mut submitted: EventWriter<TextSubmitted>,
+) {
+    let typed: Vec<char> = characters
+        .iter()
+        .map(|c| c.char)
+        .filter(|c| !c.is_control())
+        .collect();
+
+    let Some((entity, mut input)) = inputs.iter_mut().find(|(_, input)| input.focused) else {
+        return;
+    };
+
+    let accepts = input.accepts;
+    typed
+        .into_iter()
+        .filter(|c| accepts(*c))
+        .for_each(|c| input.value.push(c));
+
+    if keys.just_pressed(KeyCode::Back) {
+        input.value.pop();
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        submitted.send(TextSubmitted { input: entity });
+    }
+}
+
+/// Show the value, or the placeholder when empty, with a cursor while focused
+fn render_text_inputs(
+    inputs: Query<(&TextInput, &Children), Changed<TextInput>>,
+    mut texts: Query<&mut Text>,
+) {
+    inputs.for_each(|(input, children)| {
+        let Some(mut text) = children.first().and_then(|c| texts.get_mut(*c).ok()) else {
+            return;
+        };
+
+        let (value, alpha) = match (input.value.is_empty(), input.focused) {
+            (true, false) => (input.placeholder.to_string(), 0.5),
+            (_, true) => (format!("{}|", input.value), 1.0),
+            (false, false) => (input.value.clone(), 1.0),
+        };
+
+        text.sections[0].value = value;
+        text.sections[0].style.color.set_a(alpha);
+    });
+}
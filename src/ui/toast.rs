@@ -0,0 +1,267 @@
+use super::widget::Theme;
+use crate::{
+    constant::{HEARTBEAT, RAW_APDU},
+    error::{APDUErrorCode, DeviceHIDError},
+    event::{
+        discovery::{DiscoveryFinished, DiscoveryOutcome},
+        general::AppOpenFailed,
+        queue::{CommandCompleted, CommandLifecycle, CommandStage},
+    },
+};
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// Toasts shown at once, the oldest ones being dismissed first
+const MAX_TOASTS: usize = 5;
+
+pub struct ToastPlugin;
+
+impl Plugin for ToastPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Notify>()
+            .add_startup_system(spawn_toast_stack)
+            .add_systems((
+                notify_command_stages,
+                notify_command_errors,
+                notify_app_open_failures,
+                notify_discovery_outcomes,
+                show_toasts
+                    .after(notify_command_stages)
+                    .after(notify_command_errors)
+                    .after(notify_app_open_failures)
+                    .after(notify_discovery_outcomes),
+                expire_toasts,
+            ));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastLevel {
+    fn color(&self) -> Color {
+        match self {
+            Self::Info => Color::rgb(0.2, 0.3, 0.5),
+            Self::Success => Color::rgb(0.2, 0.45, 0.2),
+            Self::Warning => Color::rgb(0.55, 0.4, 0.1),
+            Self::Error => Color::rgb(0.55, 0.15, 0.15),
+        }
+    }
+}
+
+/// Show a message to the user in a toast, dismissed after a few seconds
+pub struct Notify {
+    pub level: ToastLevel,
+    pub message: String,
+}
+
+impl Notify {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Info, message)
+    }
+
+    pub fn success(message: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Success, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Warning, message)
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Error, message)
+    }
+
+    fn new(level: ToastLevel, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+/// Container of the toasts, in the bottom right corner of the window
+#[derive(Component)]
+struct ToastStack;
+
+#[derive(Component)]
+struct Toast {
+    level: ToastLevel,
+    message: String,
+    expires: Timer,
+}
+
+fn spawn_toast_stack(mut commands: Commands) {
+    commands.spawn((
+        ToastStack,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                ..default()
+            },
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+    ));
+}
+
+/// Heartbeats are left to the lock state, raw APDUs to the console
+fn is_notified(command: &str) -> bool {
+    command != HEARTBEAT && command != RAW_APDU
+}
+
+/// Tell the user when to look at the device, and what they decided there
+fn notify_command_stages(
+    mut events: EventReader<CommandLifecycle>,
+    mut notify: EventWriter<Notify>,
+) {
+    events.iter().filter(|e| is_notified(e.name)).for_each(|e| {
+        let toast = match e.stage {
+            CommandStage::AwaitingUserConfirmation => {
+                Notify::info(format!("{}: confirm on your device", e.name))
+            }
+            CommandStage::UserApproved => Notify::success(format!("{}: approved", e.name)),
+            CommandStage::UserRejected => Notify::warning(format!("{}: rejected", e.name)),
+            CommandStage::TimedOut => {
+                Notify::error(format!("{}: the device didn't answer in time", e.name))
+            }
+            CommandStage::Sent => return,
+        };
+        notify.send(toast);
+    });
+}
+
+/// Show the failed commands with their decoded status word
+fn notify_command_errors(
+    mut events: EventReader<CommandCompleted>,
+    mut notify: EventWriter<Notify>,
+) {
+    events.iter().filter(|e| is_notified(e.name)).for_each(|e| {
+        let message = match &e.result {
+            Ok(answer) => match APDUErrorCode::try_from(answer.retcode()) {
+                Ok(APDUErrorCode::NoError) => return,
+                // Already reported by the command's lifecycle
+                Ok(code) if code.is_user_rejection() => return,
+                Ok(code) => format!("{}: {}", e.name, code.description()),
+                Err(_) => format!("{}: unknown status {:04x}", e.name, answer.retcode()),
+            },
+            // Reported as timed out by the command's lifecycle
+            Err(err) if matches!(err.downcast_ref(), Some(DeviceHIDError::Timeout)) => return,
+            Err(err) => format!("{}: {err}", e.name),
+        };
+        notify.send(Notify::error(message));
+    });
+}
+
+fn notify_app_open_failures(
+    mut events: EventReader<AppOpenFailed>,
+    mut notify: EventWriter<Notify>,
+) {
+    events.iter().for_each(|e| {
+        notify.send(Notify::error(format!(
+            "Cannot open {}: {}",
+            e.app, e.reason
+        )));
+    });
+}
+
+fn notify_discovery_outcomes(
+    mut events: EventReader<DiscoveryFinished>,
+    mut notify: EventWriter<Notify>,
+) {
+    events.iter().for_each(|e| {
+        let toast = match &e.outcome {
+            DiscoveryOutcome::Completed => Notify::success(format!(
+                "{} discovery: {} accounts found",
+                e.app,
+                e.accounts.len()
+            )),
+            DiscoveryOutcome::Cancelled => return,
+            DiscoveryOutcome::Failed(reason) => {
+                Notify::error(format!("{} discovery failed: {reason}", e.app))
+            }
+        };
+        notify.send(toast);
+    });
+}
+
+/// Add a toast per notification, restarting the timer of an identical toast still shown instead
+fn show_toasts(
+    mut events: EventReader<Notify>,
+    stack: Query<(Entity, Option<&Children>), With<ToastStack>>,
+    theme: Res<Theme>,
+    mut toasts: Query<&mut Toast>,
+    mut commands: Commands,
+) {
+    let Ok((stack_id, children)) = stack.get_single() else {
+        return;
+    };
+    let mut shown: Vec<Entity> = children.map_or(Vec::new(), |c| c.to_vec());
+
+    events.iter().for_each(|e| {
+        let duplicate = shown.iter().copied().find(|id| {
+            toasts
+                .get(*id)
+                .is_ok_and(|t| t.level == e.level && t.message == e.message)
+        });
+        if let Some(Ok(mut toast)) = duplicate.map(|id| toasts.get_mut(id)) {
+            toast.expires.reset();
+            return;
+        }
+
+        if shown.len() >= MAX_TOASTS {
+            commands.entity(shown.remove(0)).despawn_recursive();
+        }
+
+        let toast = commands
+            .spawn((
+                Toast {
+                    level: e.level,
+                    message: e.message.clone(),
+                    expires: Timer::new(TOAST_DURATION, TimerMode::Once),
+                },
+                NodeBundle {
+                    style: Style {
+                        max_size: Size::width(Val::Px(400.0)),
+                        margin: UiRect::top(Val::Px(5.0)),
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: e.level.color().into(),
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    e.message.clone(),
+                    theme.text_style(),
+                ));
+            })
+            .id();
+        commands.entity(stack_id).add_child(toast);
+        shown.push(toast);
+    });
+}
+
+fn expire_toasts(time: Res<Time>, mut toasts: Query<(Entity, &mut Toast)>, mut commands: Commands) {
+    toasts.for_each_mut(|(toast_id, mut toast)| {
+        if toast.expires.tick(time.delta()).just_finished() {
+            commands.entity(toast_id).despawn_recursive();
+        }
+    });
+}
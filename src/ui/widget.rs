@@ -0,0 +1,235 @@
+use super::text_input::TextInput;
+use bevy::prelude::*;
+
+/// Colors, font and sizes shared by the widgets, so that the whole UI can be restyled in one place
+#[derive(Resource, Clone)]
+pub struct Theme {
+    pub font: Handle<Font>,
+    pub font_size: f32,
+    pub title_font_size: f32,
+    pub text_color: Color,
+    /// Labels, placeholders and hints
+    pub muted_text_color: Color,
+    pub button_color: Color,
+    pub hovered_button_color: Color,
+    pub pressed_button_color: Color,
+    /// Button holding the keyboard focus
+    pub focused_button_color: Color,
+    /// Background of lists
+    pub panel_color: Color,
+    pub button_size: Size,
+    /// Buttons next to an input or in a list row
+    pub compact_button_size: Size,
+    /// Space between widgets
+    pub spacing: f32,
+}
+
+impl FromWorld for Theme {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            font: world
+                .resource::<AssetServer>()
+                .load("fonts/FiraSans-Bold.ttf"),
+            font_size: 16.0,
+            title_font_size: 20.0,
+            text_color: Color::rgb(0.9, 0.9, 0.9),
+            muted_text_color: Color::rgb(0.6, 0.6, 0.6),
+            button_color: Color::rgb(0.15, 0.15, 0.15),
+            hovered_button_color: Color::rgb(0.25, 0.25, 0.25),
+            pressed_button_color: Color::rgb(0.35, 0.35, 0.35),
+            focused_button_color: Color::rgb(0.2, 0.25, 0.4),
+            panel_color: Color::rgb(0.1, 0.1, 0.1),
+            button_size: Size::new(Val::Px(120.0), Val::Px(40.0)),
+            compact_button_size: Size::new(Val::Px(80.0), Val::Px(40.0)),
+            spacing: 5.0,
+        }
+    }
+}
+
+impl Theme {
+    pub fn text_style(&self) -> TextStyle {
+        TextStyle {
+            font: self.font.clone(),
+            font_size: self.font_size,
+            color: self.text_color,
+        }
+    }
+
+    pub fn muted_text_style(&self) -> TextStyle {
+        TextStyle {
+            color: self.muted_text_color,
+            ..self.text_style()
+        }
+    }
+
+    pub fn title_style(&self) -> TextStyle {
+        TextStyle {
+            font_size: self.title_font_size,
+            ..self.text_style()
+        }
+    }
+}
+
+/// Spawn a full width section of the window, stacking its children under an optional title
+pub fn panel(
+    commands: &mut Commands,
+    theme: &Theme,
+    title: Option<&str>,
+    children: impl FnOnce(&mut ChildBuilder),
+) -> Entity {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::width(Val::Percent(100.0)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                padding: UiRect::vertical(Val::Px(2.0 * theme.spacing)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            if let Some(title) = title {
+                parent.title(theme, title);
+            }
+            children(parent);
+        })
+        .id()
+}
+
+/// Widgets spawned as children of a node. `bundle` carries the markers the systems look the widget up with.
+pub trait Widgets {
+    fn title(&mut self, theme: &Theme, title: &str) -> Entity;
+
+    /// Button showing `label`, its first child being the text
+    fn button(&mut self, theme: &Theme, label: &str, size: Size, bundle: impl Bundle) -> Entity;
+
+    /// Single line [TextInput] of the given width
+    fn text_input(
+        &mut self,
+        theme: &Theme,
+        input: TextInput,
+        width: f32,
+        bundle: impl Bundle,
+    ) -> Entity;
+
+    /// `label: value` line, the value being the second section of the text
+    fn labelled_row(&mut self, theme: &Theme, label: &str, bundle: impl Bundle) -> Entity;
+
+    /// Column on the panel background, clipping what overflows `size`
+    fn list(
+        &mut self,
+        theme: &Theme,
+        size: Size,
+        bundle: impl Bundle,
+        children: impl FnOnce(&mut ChildBuilder),
+    ) -> Entity;
+
+    /// Widgets laid out side by side
+    fn row(&mut self, children: impl FnOnce(&mut ChildBuilder)) -> Entity;
+}
+
+impl Widgets for ChildBuilder<'_, '_, '_> {
+    fn title(&mut self, theme: &Theme, title: &str) -> Entity {
+        self.spawn(TextBundle::from_section(title, theme.title_style()))
+            .id()
+    }
+
+    fn button(&mut self, theme: &Theme, label: &str, size: Size, bundle: impl Bundle) -> Entity {
+        self.spawn((
+            bundle,
+            ButtonBundle {
+                style: Style {
+                    size,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::all(Val::Px(theme.spacing / 2.0)),
+                    ..default()
+                },
+                background_color: theme.button_color.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, theme.text_style()));
+        })
+        .id()
+    }
+
+    fn text_input(
+        &mut self,
+        theme: &Theme,
+        input: TextInput,
+        width: f32,
+        bundle: impl Bundle,
+    ) -> Entity {
+        self.spawn((
+            bundle,
+            input,
+            ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(width), theme.button_size.height),
+                    align_items: AlignItems::Center,
+                    margin: UiRect::all(Val::Px(theme.spacing / 2.0)),
+                    padding: UiRect::horizontal(Val::Px(2.0 * theme.spacing)),
+                    ..default()
+                },
+                background_color: theme.button_color.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("", theme.text_style()));
+        })
+        .id()
+    }
+
+    fn labelled_row(&mut self, theme: &Theme, label: &str, bundle: impl Bundle) -> Entity {
+        self.spawn((
+            bundle,
+            TextBundle::from_sections([
+                TextSection::new(format!("{label}: "), theme.muted_text_style()),
+                TextSection::new("", theme.text_style()),
+            ]),
+        ))
+        .id()
+    }
+
+    fn list(
+        &mut self,
+        theme: &Theme,
+        size: Size,
+        bundle: impl Bundle,
+        children: impl FnOnce(&mut ChildBuilder),
+    ) -> Entity {
+        self.spawn((
+            bundle,
+            NodeBundle {
+                style: Style {
+                    size,
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::top(Val::Px(theme.spacing)),
+                    padding: UiRect::all(Val::Px(theme.spacing)),
+                    overflow: Overflow::Hidden,
+                    ..default()
+                },
+                background_color: theme.panel_color.into(),
+                ..default()
+            },
+        ))
+        .with_children(children)
+        .id()
+    }
+
+    fn row(&mut self, children: impl FnOnce(&mut ChildBuilder)) -> Entity {
+        self.spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(children)
+        .id()
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use bevy_ledger::{
+    config::LedgerConfig,
+    device::{Device, DeviceKind, DeviceModel, DeviceVersion, LockState, RunningApp},
+    ethereum::{EthCommand, EthResponse, Ethereum},
+    event::{
+        app::{AppCommand, AppResponse},
+        general::{
+            AnswerMalformed, AppAndVersionReceived, GetAppAndVersion, GetVersion, VersionReceived,
+        },
+        lock::{DeviceLocked, DeviceUnlocked},
+    },
+    queue::CommandQueue,
+    replay::{ReplayMode, ReplaySession},
+    LedgerPlugins,
+};
+use std::time::{Duration, Instant};
+
+const SESSION: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/session.jsonl");
+const APP_SWITCH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/app_switch.jsonl"
+);
+const MALFORMED: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/malformed.jsonl"
+);
+const LOCKED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/locked.jsonl");
+
+/// Headless app with a device replaying a recorded session
+fn replaying_app(session: &str) -> (App, Entity) {
+    let session = ReplaySession::load(session, ReplayMode::Strict).unwrap();
+
+    let mut app = App::new();
+    app.insert_resource(LedgerConfig {
+        heartbeat_interval: None,
+        ..default()
+    })
+    .add_plugins(MinimalPlugins)
+    .add_plugins(LedgerPlugins);
+
+    let device_id = app
+        .world
+        .spawn((
+            Device::replay(session),
+            CommandQueue::default(),
+            LockState::default(),
+        ))
+        .id();
+
+    (app, device_id)
+}
+
+/// Update the app until an event `E` gets sent, the exchanges running on the task pools
+fn wait_for<E: Event, T>(app: &mut App, read: impl Fn(&E) -> T) -> T {
+    let mut reader = ManualEventReader::<E>::default();
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    while Instant::now() < deadline {
+        app.update();
+        let events = app.world.resource::<Events<E>>();
+        if let Some(event) = reader.iter(events).next() {
+            return read(event);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    panic!("timed out waiting for {}", std::any::type_name::<E>());
+}
+
+fn remaining(app: &App, device_id: Entity) -> usize {
+    match app.world.get::<Device>(device_id).unwrap().kind() {
+        DeviceKind::Replay(session) => session.remaining(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn replays_recorded_session() {
+    let (mut app, device_id) = replaying_app(SESSION);
+    assert_eq!(
+        app.world.get::<Device>(device_id).unwrap().model(),
+        DeviceModel::NanoX
+    );
+    assert_eq!(remaining(&app, device_id), 2);
+
+    // Commands are sent one after the other, as the session was recorded in strict order
+    app.world.send_event(GetVersion { device_id });
+    let version = wait_for(&mut app, |e: &VersionReceived| e.version.clone());
+    assert_eq!(version.target_id, 0x3300_0004);
+    assert_eq!(version.se_version, "2.2.3");
+    assert_eq!(version.mcu_version, "2.30");
+
+    app.world.send_event(GetAppAndVersion { device_id });
+    let running = wait_for(&mut app, |e: &AppAndVersionReceived| e.app.clone());
+    assert!(running.is_dashboard());
+    assert_eq!(running.version, "2.2.3");
+
+    app.update();
+    let device = app.world.entity(device_id);
+    assert_eq!(device.get::<DeviceVersion>(), Some(&version));
+    assert_eq!(device.get::<RunningApp>(), Some(&running));
+    assert_eq!(device.get::<LockState>(), Some(&LockState::Unlocked));
+    assert_eq!(remaining(&app, device_id), 0);
+}
+
+#[test]
+fn reports_malformed_answers() {
+    let (mut app, device_id) = replaying_app(MALFORMED);
+
+    // The version is cut short
+    app.world.send_event(GetVersion { device_id });
+    let name = wait_for(&mut app, |e: &AnswerMalformed| e.name);
+    assert_eq!(name, "GetVersion");
+    assert!(app.world.get::<DeviceVersion>(device_id).is_none());
+}
+
+#[test]
+fn replays_locked_device() {
+    let (mut app, device_id) = replaying_app(LOCKED);
+
+    app.world.send_event(GetAppAndVersion { device_id });
+    wait_for(&mut app, |e: &DeviceLocked| e.device_id);
+    let lock_state = app.world.get::<LockState>(device_id);
+    assert_eq!(lock_state, Some(&LockState::Locked));
+
+    // The user entered their PIN
+    app.world.send_event(GetAppAndVersion { device_id });
+    wait_for(&mut app, |e: &DeviceUnlocked| e.device_id);
+    let lock_state = app.world.get::<LockState>(device_id);
+    assert_eq!(lock_state, Some(&LockState::Unlocked));
+    assert_eq!(remaining(&app, device_id), 0);
+}
+
+#[test]
+fn replays_app_switch() {
+    let (mut app, device_id) = replaying_app(APP_SWITCH);
+
+    // Bitcoin is running: quit it, then open Ethereum from the dashboard before sending the command
+    let command = EthCommand::GetAddress {
+        path: "m/44'/60'/0'/0/0".parse().unwrap(),
+        display: false,
+        chain_code: false,
+    };
+    app.world
+        .send_event(AppCommand::<Ethereum> { device_id, command });
+    let address = wait_for(&mut app, |e: &AppResponse<Ethereum>| match &e.result {
+        Ok(EthResponse::Address { address, .. }) => address.clone(),
+        result => panic!("unexpected response {result:?}"),
+    });
+    assert_eq!(address, "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+
+    let running = app.world.get::<RunningApp>(device_id).unwrap();
+    assert_eq!(running.name, "Ethereum");
+    assert_eq!(remaining(&app, device_id), 0);
+}